@@ -0,0 +1,749 @@
+use crate::*;
+use std::sync::mpsc::{self, Receiver};
+use std::time::Instant;
+
+const MATE: i32 = 100_000;
+
+// PIECE-SQUARE TABLES FOR WHITE, SAME LAYOUT AS THE BOARD STRING (a8 FIRST)
+#[rustfmt::skip]
+const PAWN_TABLE: [i32; 64] = [
+     0,  0,  0,  0,  0,  0,  0,  0,
+    50, 50, 50, 50, 50, 50, 50, 50,
+    10, 10, 20, 30, 30, 20, 10, 10,
+     5,  5, 10, 25, 25, 10,  5,  5,
+     0,  0,  0, 20, 20,  0,  0,  0,
+     5, -5,-10,  0,  0,-10, -5,  5,
+     5, 10, 10,-20,-20, 10, 10,  5,
+     0,  0,  0,  0,  0,  0,  0,  0,
+];
+
+#[rustfmt::skip]
+const KNIGHT_TABLE: [i32; 64] = [
+    -50,-40,-30,-30,-30,-30,-40,-50,
+    -40,-20,  0,  0,  0,  0,-20,-40,
+    -30,  0, 10, 15, 15, 10,  0,-30,
+    -30,  5, 15, 20, 20, 15,  5,-30,
+    -30,  0, 15, 20, 20, 15,  0,-30,
+    -30,  5, 10, 15, 15, 10,  5,-30,
+    -40,-20,  0,  5,  5,  0,-20,-40,
+    -50,-40,-30,-30,-30,-30,-40,-50,
+];
+
+#[rustfmt::skip]
+const BISHOP_TABLE: [i32; 64] = [
+    -20,-10,-10,-10,-10,-10,-10,-20,
+    -10,  0,  0,  0,  0,  0,  0,-10,
+    -10,  0,  5, 10, 10,  5,  0,-10,
+    -10,  5,  5, 10, 10,  5,  5,-10,
+    -10,  0, 10, 10, 10, 10,  0,-10,
+    -10, 10, 10, 10, 10, 10, 10,-10,
+    -10,  5,  0,  0,  0,  0,  5,-10,
+    -20,-10,-10,-10,-10,-10,-10,-20,
+];
+
+#[rustfmt::skip]
+const ROOK_TABLE: [i32; 64] = [
+     0,  0,  0,  0,  0,  0,  0,  0,
+     5, 10, 10, 10, 10, 10, 10,  5,
+    -5,  0,  0,  0,  0,  0,  0, -5,
+    -5,  0,  0,  0,  0,  0,  0, -5,
+    -5,  0,  0,  0,  0,  0,  0, -5,
+    -5,  0,  0,  0,  0,  0,  0, -5,
+    -5,  0,  0,  0,  0,  0,  0, -5,
+     0,  0,  0,  5,  5,  0,  0,  0,
+];
+
+#[rustfmt::skip]
+const QUEEN_TABLE: [i32; 64] = [
+    -20,-10,-10, -5, -5,-10,-10,-20,
+    -10,  0,  0,  0,  0,  0,  0,-10,
+    -10,  0,  5,  5,  5,  5,  0,-10,
+     -5,  0,  5,  5,  5,  5,  0, -5,
+      0,  0,  5,  5,  5,  5,  0, -5,
+    -10,  5,  5,  5,  5,  5,  0,-10,
+    -10,  0,  5,  0,  0,  0,  0,-10,
+    -20,-10,-10, -5, -5,-10,-10,-20,
+];
+
+#[rustfmt::skip]
+const KING_TABLE: [i32; 64] = [
+    -30,-40,-40,-50,-50,-40,-40,-30,
+    -30,-40,-40,-50,-50,-40,-40,-30,
+    -30,-40,-40,-50,-50,-40,-40,-30,
+    -30,-40,-40,-50,-50,-40,-40,-30,
+    -20,-30,-30,-40,-40,-30,-30,-20,
+    -10,-20,-20,-20,-20,-20,-20,-10,
+     20, 20,  0,  0,  0,  0, 20, 20,
+     20, 30, 10,  0,  0, 10, 30, 20,
+];
+
+const KNIGHT_STEPS: [(i32, i32); 8] = [
+    (-2, -1),
+    (-2, 1),
+    (-1, -2),
+    (-1, 2),
+    (1, -2),
+    (1, 2),
+    (2, -1),
+    (2, 1),
+];
+const KING_STEPS: [(i32, i32); 8] = [
+    (-1, -1),
+    (-1, 0),
+    (-1, 1),
+    (0, -1),
+    (0, 1),
+    (1, -1),
+    (1, 0),
+    (1, 1),
+];
+const DIAGONALS: [(i32, i32); 4] = [(-1, -1), (-1, 1), (1, -1), (1, 1)];
+const LINES: [(i32, i32); 4] = [(-1, 0), (1, 0), (0, -1), (0, 1)];
+
+#[derive(Clone, Copy)]
+pub struct AiOptions {
+    pub depth: u32,
+    pub time: Duration,
+}
+
+#[derive(Clone, Copy, PartialEq)]
+struct AiMove {
+    from: usize,
+    to: usize,
+    promotion: Option<char>,
+}
+
+// Small board used only for searching, castling rights and en passant are
+// not tracked so those moves are only played when they come from the real board
+#[derive(Clone)]
+struct AiBoard {
+    squares: [char; 64],
+    white: bool,
+}
+
+fn is_white(piece: char) -> bool {
+    piece.is_ascii_lowercase()
+}
+
+fn square(row: i32, col: i32) -> Option<usize> {
+    if (0..8).contains(&row) && (0..8).contains(&col) {
+        Some((row * 8 + col) as usize)
+    } else {
+        None
+    }
+}
+
+fn piece_value(piece: char) -> i32 {
+    match piece.to_ascii_lowercase() {
+        'p' => 100,
+        'n' => 320,
+        'b' => 330,
+        'r' => 500,
+        'q' => 900,
+        _ => 0,
+    }
+}
+
+impl AiBoard {
+    fn new(board_str: &str, turn: Color) -> Self {
+        let mut squares = ['.'; 64];
+
+        for (i, c) in board_str.chars().take(64).enumerate() {
+            if get_piece_color(c) != Color::None {
+                squares[i] = c;
+            }
+        }
+
+        Self {
+            squares,
+            white: turn == Color::White,
+        }
+    }
+
+    fn own(&self, piece: char) -> bool {
+        piece != '.' && is_white(piece) == self.white
+    }
+
+    fn enemy(&self, piece: char) -> bool {
+        piece != '.' && is_white(piece) != self.white
+    }
+
+    fn make(&self, m: AiMove) -> AiBoard {
+        let mut next = self.clone();
+        let piece = next.squares[m.from];
+        let kind = piece.to_ascii_lowercase();
+
+        // EN PASSANT, THE CAPTURED PAWN IS BESIDE THE MOVING ONE
+        if kind == 'p' && m.from % 8 != m.to % 8 && next.squares[m.to] == '.' {
+            next.squares[m.from / 8 * 8 + m.to % 8] = '.';
+        }
+
+        // CASTLING, MOVE THE ROOK AS WELL
+        if kind == 'k' && m.from.abs_diff(m.to) == 2 {
+            let row = m.from / 8 * 8;
+            let (rook_from, rook_to) = if m.to > m.from {
+                (row + 7, m.from + 1)
+            } else {
+                (row, m.from - 1)
+            };
+            next.squares[rook_to] = next.squares[rook_from];
+            next.squares[rook_from] = '.';
+        }
+
+        next.squares[m.to] = match m.promotion {
+            Some(p) if self.white => p.to_ascii_lowercase(),
+            Some(p) => p.to_ascii_uppercase(),
+            None => piece,
+        };
+        next.squares[m.from] = '.';
+        next.white = !next.white;
+        next
+    }
+
+    // Is `sq` attacked by the given side
+    fn attacked(&self, sq: usize, by_white: bool) -> bool {
+        let (row, col) = ((sq / 8) as i32, (sq % 8) as i32);
+        let is_attacker = |idx: usize, kinds: &str| {
+            let p = self.squares[idx];
+            p != '.' && is_white(p) == by_white && kinds.contains(p.to_ascii_lowercase())
+        };
+
+        // WHITE PAWNS ATTACK UPWARDS, SO THEY SIT BELOW THE SQUARE
+        let pawn_row = if by_white { row + 1 } else { row - 1 };
+        for dc in [-1, 1] {
+            if square(pawn_row, col + dc).is_some_and(|idx| is_attacker(idx, "p")) {
+                return true;
+            }
+        }
+
+        for (dr, dc) in KNIGHT_STEPS {
+            if square(row + dr, col + dc).is_some_and(|idx| is_attacker(idx, "n")) {
+                return true;
+            }
+        }
+
+        for (dr, dc) in KING_STEPS {
+            if square(row + dr, col + dc).is_some_and(|idx| is_attacker(idx, "k")) {
+                return true;
+            }
+        }
+
+        for (dirs, kinds) in [(DIAGONALS, "bq"), (LINES, "rq")] {
+            for (dr, dc) in dirs {
+                let (mut r, mut c) = (row + dr, col + dc);
+                while let Some(idx) = square(r, c) {
+                    if self.squares[idx] != '.' {
+                        if is_attacker(idx, kinds) {
+                            return true;
+                        }
+                        break;
+                    }
+                    r += dr;
+                    c += dc;
+                }
+            }
+        }
+
+        false
+    }
+
+    fn in_check(&self) -> bool {
+        let king = if self.white { 'k' } else { 'K' };
+        match self.squares.iter().position(|&p| p == king) {
+            Some(sq) => self.attacked(sq, !self.white),
+            None => false,
+        }
+    }
+
+    fn pseudo_moves(&self) -> Vec<AiMove> {
+        let mut moves = Vec::new();
+
+        for from in 0..64 {
+            let piece = self.squares[from];
+            if !self.own(piece) {
+                continue;
+            }
+
+            let (row, col) = ((from / 8) as i32, (from % 8) as i32);
+            let mut push = |to: usize| {
+                moves.push(AiMove {
+                    from,
+                    to,
+                    promotion: None,
+                })
+            };
+
+            match piece.to_ascii_lowercase() {
+                'p' => {
+                    let (dir, start, last) = if self.white { (-1, 6, 0) } else { (1, 1, 7) };
+                    let mut targets = Vec::new();
+
+                    if let Some(to) = square(row + dir, col).filter(|&to| self.squares[to] == '.') {
+                        targets.push(to);
+
+                        let double = square(row + 2 * dir, col);
+                        if let Some(to) =
+                            double.filter(|&to| row == start && self.squares[to] == '.')
+                        {
+                            targets.push(to);
+                        }
+                    }
+
+                    for dc in [-1, 1] {
+                        if let Some(to) = square(row + dir, col + dc) {
+                            if self.enemy(self.squares[to]) {
+                                targets.push(to);
+                            }
+                        }
+                    }
+
+                    for to in targets {
+                        let promotion = if (to / 8) as i32 == last {
+                            Some('q')
+                        } else {
+                            None
+                        };
+                        moves.push(AiMove {
+                            from,
+                            to,
+                            promotion,
+                        });
+                    }
+                }
+                kind @ ('n' | 'k') => {
+                    let steps = if kind == 'n' {
+                        KNIGHT_STEPS
+                    } else {
+                        KING_STEPS
+                    };
+
+                    for (dr, dc) in steps {
+                        if let Some(to) = square(row + dr, col + dc) {
+                            if !self.own(self.squares[to]) {
+                                push(to);
+                            }
+                        }
+                    }
+                }
+                kind => {
+                    let dirs: Vec<(i32, i32)> = match kind {
+                        'b' => DIAGONALS.to_vec(),
+                        'r' => LINES.to_vec(),
+                        _ => DIAGONALS.iter().chain(LINES.iter()).copied().collect(),
+                    };
+
+                    for (dr, dc) in dirs {
+                        let (mut r, mut c) = (row + dr, col + dc);
+                        while let Some(to) = square(r, c) {
+                            let target = self.squares[to];
+                            if self.own(target) {
+                                break;
+                            }
+                            push(to);
+                            if target != '.' {
+                                break;
+                            }
+                            r += dr;
+                            c += dc;
+                        }
+                    }
+                }
+            }
+        }
+
+        moves
+    }
+
+    fn legal_moves(&self) -> Vec<AiMove> {
+        self.pseudo_moves()
+            .into_iter()
+            .filter(|m| {
+                let next = self.make(*m);
+                let king = if self.white { 'k' } else { 'K' };
+                match next.squares.iter().position(|&p| p == king) {
+                    Some(sq) => !next.attacked(sq, next.white),
+                    None => true,
+                }
+            })
+            .collect()
+    }
+
+    // Material and piece placement, from the side to move's point of view
+    fn evaluate(&self) -> i32 {
+        let mut score = 0;
+
+        for (i, &piece) in self.squares.iter().enumerate() {
+            if piece == '.' {
+                continue;
+            }
+
+            // TABLES ARE FOR WHITE, MIRROR THE ROW FOR BLACK
+            let idx = if is_white(piece) {
+                i
+            } else {
+                (7 - i / 8) * 8 + i % 8
+            };
+            let table = match piece.to_ascii_lowercase() {
+                'p' => PAWN_TABLE,
+                'n' => KNIGHT_TABLE,
+                'b' => BISHOP_TABLE,
+                'r' => ROOK_TABLE,
+                'q' => QUEEN_TABLE,
+                _ => KING_TABLE,
+            };
+
+            let value = piece_value(piece) + table[idx];
+            score += if is_white(piece) { value } else { -value };
+        }
+
+        if self.white {
+            score
+        } else {
+            -score
+        }
+    }
+
+    // Captures first, most valuable victim by least valuable attacker
+    fn order(&self, moves: &mut [AiMove]) {
+        moves.sort_by_key(|m| {
+            let victim = piece_value(self.squares[m.to]);
+            let attacker = piece_value(self.squares[m.from]);
+            if victim > 0 {
+                -(victim * 10 - attacker)
+            } else {
+                0
+            }
+        });
+    }
+}
+
+struct Search {
+    deadline: Instant,
+    nodes: u64,
+    aborted: bool,
+}
+
+impl Search {
+    fn out_of_time(&mut self) -> bool {
+        self.nodes += 1;
+        if self.nodes & 1023 == 0 && Instant::now() > self.deadline {
+            self.aborted = true;
+        }
+        self.aborted
+    }
+
+    fn quiesce(&mut self, board: &AiBoard, mut alpha: i32, beta: i32) -> i32 {
+        if self.out_of_time() {
+            return 0;
+        }
+
+        let stand_pat = board.evaluate();
+        if stand_pat >= beta {
+            return beta;
+        }
+        alpha = alpha.max(stand_pat);
+
+        let mut captures: Vec<AiMove> = board
+            .legal_moves()
+            .into_iter()
+            .filter(|m| board.squares[m.to] != '.')
+            .collect();
+        board.order(&mut captures);
+
+        for m in captures {
+            let score = -self.quiesce(&board.make(m), -beta, -alpha);
+            if self.aborted {
+                return 0;
+            }
+            if score >= beta {
+                return beta;
+            }
+            alpha = alpha.max(score);
+        }
+
+        alpha
+    }
+
+    fn negamax(&mut self, board: &AiBoard, depth: u32, mut alpha: i32, beta: i32, ply: i32) -> i32 {
+        if self.out_of_time() {
+            return 0;
+        }
+
+        if depth == 0 {
+            return self.quiesce(board, alpha, beta);
+        }
+
+        let mut moves = board.legal_moves();
+
+        if moves.is_empty() {
+            return if board.in_check() { -MATE + ply } else { 0 };
+        }
+
+        board.order(&mut moves);
+
+        for m in moves {
+            let score = -self.negamax(&board.make(m), depth - 1, -beta, -alpha, ply + 1);
+            if self.aborted {
+                return 0;
+            }
+            if score >= beta {
+                return beta;
+            }
+            alpha = alpha.max(score);
+        }
+
+        alpha
+    }
+}
+
+// Iterative deepening over the root moves until the depth or time limit is hit
+fn search(board: AiBoard, mut root: Vec<AiMove>, opts: &AiOptions) -> Option<AiMove> {
+    let mut search = Search {
+        deadline: Instant::now() + opts.time,
+        nodes: 0,
+        aborted: false,
+    };
+
+    board.order(&mut root);
+    let mut best = *root.first()?;
+
+    for depth in 1..=opts.depth.max(1) {
+        let mut alpha = -MATE - 1;
+        let mut depth_best = best;
+
+        // SEARCH THE PREVIOUS BEST MOVE FIRST
+        root.sort_by_key(|m| *m != best);
+
+        for m in root.iter() {
+            let score = -search.negamax(&board.make(*m), depth - 1, -MATE - 1, -alpha, 1);
+            if search.aborted {
+                break;
+            }
+            if score > alpha {
+                alpha = score;
+                depth_best = *m;
+            }
+        }
+
+        // A PARTIAL ITERATION STILL SEARCHED THE PREVIOUS BEST FIRST
+        best = depth_best;
+
+        if search.aborted || alpha >= MATE - 100 {
+            break;
+        }
+    }
+
+    Some(best)
+}
+
+// Computer opponent that searches in a background thread
+pub struct Ai {
+    opts: AiOptions,
+    position: Option<(AiBoard, Vec<AiMove>)>,
+    searching: Option<Receiver<Option<AiMove>>>,
+}
+
+impl Ai {
+    pub fn new(opts: AiOptions) -> Self {
+        Self {
+            opts,
+            position: None,
+            searching: None,
+        }
+    }
+}
+
+impl Opponent for Ai {
+    fn poll_move(&mut self) -> Option<net::Move> {
+        if self.searching.is_none() {
+            let (board, root) = self.position.clone()?;
+            let opts = self.opts;
+            let (tx, rx) = mpsc::channel();
+
+            std::thread::spawn(move || {
+                let _ = tx.send(search(board, root, &opts));
+            });
+
+            self.searching = Some(rx);
+        }
+
+        let m = self.searching.as_ref()?.try_recv().ok()?;
+        self.searching = None;
+        let m = m?;
+
+        Some(net::Move {
+            from: pos_int_to_tuple(m.from),
+            to: pos_int_to_tuple(m.to),
+            offer_draw: false,
+            promotion: m.promotion.and_then(char_to_promotion),
+            forfeit: false,
+        })
+    }
+
+    fn ack(&mut self, ack: net::Ack) {
+        if !ack.ok {
+            log!(LogLevel::Warn, "Computer played an illegal move");
+        }
+    }
+
+    fn position_changed(&mut self, position: &PositionInfo) {
+        // ROOT MOVES COME FROM THE REAL BOARD SO CASTLING AND EN PASSANT ARE INCLUDED
+        let mut root = Vec::new();
+        for (from, targets) in position.valid_moves.iter().enumerate() {
+            for &to in targets {
+                let promotion = if is_promotion(position.board_str, from, to) {
+                    Some('q')
+                } else {
+                    None
+                };
+                let m = AiMove {
+                    from,
+                    to,
+                    promotion,
+                };
+                if !root.contains(&m) {
+                    root.push(m);
+                }
+            }
+        }
+
+        // A SEARCH ON THE OLD POSITION IS DROPPED, ITS RESULT GOES NOWHERE
+        self.searching = None;
+        self.position = Some((AiBoard::new(position.board_str, position.turn), root));
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::chess::tests::board;
+
+    fn ai_board(placement: &str, turn: Color) -> AiBoard {
+        AiBoard::new(&board(placement), turn)
+    }
+
+    fn ai_move(from: &str, to: &str) -> AiMove {
+        AiMove {
+            from: str_to_idx(from),
+            to: str_to_idx(to),
+            promotion: None,
+        }
+    }
+
+    fn squares(b: &AiBoard) -> String {
+        b.squares.iter().collect()
+    }
+
+    fn targets(b: &AiBoard, from: &str) -> Vec<String> {
+        let mut targets: Vec<String> = b
+            .legal_moves()
+            .iter()
+            .filter(|m| m.from == str_to_idx(from))
+            .map(|m| idx_to_str(m.to))
+            .collect();
+        targets.sort();
+        targets
+    }
+
+    #[test]
+    fn search_finds_mate_in_one_over_material() {
+        let opts = AiOptions {
+            depth: 2,
+            time: Duration::from_secs(10),
+        };
+
+        let white = ai_board("7k/6pp/8/8/8/8/8/R1b3K1", Color::White);
+        let best = search(white.clone(), white.legal_moves(), &opts).unwrap();
+        assert!(best == ai_move("a1", "a8"));
+
+        let black = ai_board("r1B3k1/8/8/8/8/8/6PP/7K", Color::Black);
+        let best = search(black.clone(), black.legal_moves(), &opts).unwrap();
+        assert!(best == ai_move("a8", "a1"));
+    }
+
+    #[test]
+    fn legal_moves_keep_the_king_out_of_check() {
+        // THE BISHOP IS PINNED TO ITS KING
+        let b = ai_board("4r2k/8/8/8/8/8/4B3/4K3", Color::White);
+        assert!(b.pseudo_moves().iter().any(|m| m.from == str_to_idx("e2")));
+        assert!(targets(&b, "e2").is_empty());
+
+        // BLACK PAWNS ATTACK DOWNWARDS, SO d2 AND f2 ARE COVERED BUT e2 IS NOT
+        let b = ai_board("7k/8/8/8/8/4p3/8/4K3", Color::White);
+        assert_eq!(targets(&b, "e1"), ["d1", "e2", "f1"]);
+
+        let b = ai_board("4k3/8/4P3/8/8/8/8/7K", Color::Black);
+        assert_eq!(targets(&b, "e8"), ["d8", "e7", "f8"]);
+    }
+
+    #[test]
+    fn make_moves_the_castling_rook() {
+        let b = ai_board("r3k2r/8/8/8/8/8/8/R3K2R", Color::White);
+
+        let next = b.make(ai_move("e1", "g1"));
+        assert_eq!(squares(&next), board("r3k2r/8/8/8/8/8/8/R4RK1"));
+        assert!(!next.white);
+
+        let next = next.make(ai_move("e8", "c8"));
+        assert_eq!(squares(&next), board("2kr3r/8/8/8/8/8/8/R4RK1"));
+    }
+
+    #[test]
+    fn make_takes_en_passant_and_promotes() {
+        let b = ai_board("8/8/8/3pP3/8/8/8/8", Color::White);
+        let next = b.make(ai_move("e5", "d6"));
+        assert_eq!(squares(&next), board("8/8/3P4/8/8/8/8/8"));
+
+        let b = ai_board("8/8/8/8/3Pp3/8/8/8", Color::Black);
+        let next = b.make(ai_move("e4", "d3"));
+        assert_eq!(squares(&next), board("8/8/8/8/8/3p4/8/8"));
+
+        let b = ai_board("8/1P6/8/8/8/8/6p1/8", Color::White);
+        let next = b.make(AiMove {
+            promotion: Some('q'),
+            ..ai_move("b7", "b8")
+        });
+        assert_eq!(squares(&next), board("1Q6/8/8/8/8/8/6p1/8"));
+
+        let next = next.make(AiMove {
+            promotion: Some('n'),
+            ..ai_move("g2", "g1")
+        });
+        assert_eq!(squares(&next), board("1Q6/8/8/8/8/8/8/6n1"));
+    }
+
+    #[test]
+    fn promotions_become_queen_root_moves() {
+        let board_str = board("r6k/1P6/8/8/8/8/8/4K3");
+        let mut valid_moves: [Vec<usize>; 64] = std::array::from_fn(|_| Vec::new());
+        for (from, to) in [("b7", "b8"), ("b7", "a8"), ("e1", "e2")] {
+            valid_moves[str_to_idx(from)].push(str_to_idx(to));
+        }
+
+        let mut ai = Ai::new(AiOptions {
+            depth: 1,
+            time: Duration::from_secs(10),
+        });
+        ai.position_changed(&PositionInfo {
+            start_fen: None,
+            moves: &[],
+            board_str: &board_str,
+            turn: Color::White,
+            valid_moves: &valid_moves,
+        });
+
+        let (_, root) = ai.position.as_ref().unwrap();
+        let root: Vec<(String, String, Option<char>)> = root
+            .iter()
+            .map(|m| (idx_to_str(m.from), idx_to_str(m.to), m.promotion))
+            .collect();
+        assert_eq!(
+            root,
+            [
+                ("b7".to_string(), "b8".to_string(), Some('q')),
+                ("b7".to_string(), "a8".to_string(), Some('q')),
+                ("e1".to_string(), "e2".to_string(), None),
+            ]
+        );
+    }
+}
@@ -14,6 +14,12 @@ pub enum Status {
     Stalemate,
     FiftyMoveRule,
     ThreefoldRepetition,
+    Timeout,
+    // The color that gave up
+    Resignation(Color),
+    DrawAgreed,
+    // The color that kept sending illegal moves
+    IllegalMoves(Color),
 }
 
 pub fn str_to_idx(s: &str) -> usize {
@@ -31,6 +37,184 @@ pub fn idx_to_str(idx: usize) -> String {
     format!("{}{}", x, y)
 }
 
+pub fn turn_from_boardinfo(info: &str) -> Color {
+    if &info[2..3] == "W" {
+        Color::White
+    } else {
+        Color::Black
+    }
+}
+
+pub fn validate_fen(fen: &str) -> Result<(), String> {
+    let fields: Vec<&str> = fen.split_whitespace().collect();
+
+    if fields.len() != 4 && fields.len() != 6 {
+        return Err(format!("expected 4 or 6 fields, got {}", fields.len()));
+    }
+
+    let ranks: Vec<&str> = fields[0].split('/').collect();
+
+    if ranks.len() != 8 {
+        return Err(format!("expected 8 ranks, got {}", ranks.len()));
+    }
+
+    let mut kings = (0, 0);
+
+    for (i, rank) in ranks.iter().enumerate() {
+        let mut files = 0;
+
+        for c in rank.chars() {
+            match c {
+                '1'..='8' => files += c as usize - '0' as usize,
+                'p' | 'r' | 'n' | 'b' | 'q' | 'k' | 'P' | 'R' | 'N' | 'B' | 'Q' | 'K' => {
+                    if (c == 'p' || c == 'P') && (i == 0 || i == 7) {
+                        return Err(format!("pawn on rank {}", 8 - i));
+                    }
+                    if c == 'K' {
+                        kings.0 += 1;
+                    } else if c == 'k' {
+                        kings.1 += 1;
+                    }
+                    files += 1;
+                }
+                _ => return Err(format!("invalid piece '{}'", c)),
+            }
+        }
+
+        if files != 8 {
+            return Err(format!("rank {} has {} files", 8 - i, files));
+        }
+    }
+
+    if kings != (1, 1) {
+        return Err("each side must have exactly one king".to_string());
+    }
+
+    if fields[1] != "w" && fields[1] != "b" {
+        return Err(format!("invalid side to move '{}'", fields[1]));
+    }
+
+    let castling = fields[2];
+    if castling != "-" && (castling.is_empty() || !castling.chars().all(|c| "KQkq".contains(c))) {
+        return Err(format!("invalid castling rights '{}'", castling));
+    }
+
+    let ep = fields[3];
+    if ep != "-" {
+        let valid = ep.len() == 2
+            && ('a'..='h').contains(&ep.chars().next().unwrap())
+            && (ep.ends_with('3') || ep.ends_with('6'));
+        if !valid {
+            return Err(format!("invalid en passant square '{}'", ep));
+        }
+    }
+
+    let counters_valid = fields[4..].iter().all(|f| f.parse::<u32>().is_ok());
+    if !counters_valid {
+        return Err("invalid move counters".to_string());
+    }
+
+    Ok(())
+}
+
+pub const START_FEN: &str = "rnbqkbnr/pppppppp/8/8/8/8/PPPPPPPP/RNBQKBNR w KQkq - 0 1";
+
+// FEN of the current position, castling rights and move counters are followed from the start position
+pub fn position_fen(
+    board_str: &str,
+    turn: Color,
+    start_fen: Option<&str>,
+    moves: &[PlayedMove],
+) -> String {
+    let start: Vec<&str> = start_fen.unwrap_or(START_FEN).split_whitespace().collect();
+    let pieces: Vec<char> = board_str.chars().collect();
+
+    // PIECE PLACEMENT, FEN USES UPPERCASE FOR WHITE
+    let mut placement = String::new();
+    for row in 0..8 {
+        let mut empty = 0;
+        for col in 0..8 {
+            let c = pieces.get(row * 8 + col).copied().unwrap_or('.');
+            match get_piece_color(c) {
+                Color::None => empty += 1,
+                color => {
+                    if empty > 0 {
+                        placement.push_str(&empty.to_string());
+                        empty = 0;
+                    }
+                    if color == Color::White {
+                        placement.push(c.to_ascii_uppercase());
+                    } else {
+                        placement.push(c.to_ascii_lowercase());
+                    }
+                }
+            }
+        }
+        if empty > 0 {
+            placement.push_str(&empty.to_string());
+        }
+        if row < 7 {
+            placement.push('/');
+        }
+    }
+
+    // A RIGHT IS LOST ONCE ITS KING OR ROOK SQUARE IS MOVED FROM OR CAPTURED ON
+    let mut castling = start[2].replace('-', "");
+    for m in moves {
+        for square in [m.from, m.to] {
+            let lost = match idx_to_str(square).as_str() {
+                "e1" => "KQ",
+                "h1" => "K",
+                "a1" => "Q",
+                "e8" => "kq",
+                "h8" => "k",
+                "a8" => "q",
+                _ => "",
+            };
+            castling.retain(|c| !lost.contains(c));
+        }
+    }
+    if castling.is_empty() {
+        castling.push('-');
+    }
+
+    let is_pawn_move = |m: &PlayedMove| m.san.starts_with(|c: char| ('a'..='h').contains(&c));
+
+    let en_passant = match moves.last() {
+        Some(m) if is_pawn_move(m) && m.from.abs_diff(m.to) == 16 => {
+            idx_to_str((m.from + m.to) / 2)
+        }
+        None => start[3].to_string(),
+        _ => "-".to_string(),
+    };
+
+    let mut halfmove: u32 = start.get(4).and_then(|n| n.parse().ok()).unwrap_or(0);
+    for m in moves {
+        if is_pawn_move(m) || m.san.contains('x') {
+            halfmove = 0;
+        } else {
+            halfmove += 1;
+        }
+    }
+
+    let fullmove: usize = start.get(5).and_then(|n| n.parse().ok()).unwrap_or(1);
+    let black_first = if start[1] == "b" { 1 } else { 0 };
+    let fullmove = fullmove + (moves.len() + black_first) / 2;
+
+    let turn = if turn == Color::White { "w" } else { "b" };
+
+    format!(
+        "{} {} {} {} {} {}",
+        placement, turn, castling, en_passant, halfmove, fullmove
+    )
+}
+
+pub fn load_fen(board: &mut Board, fen: &str) -> Result<(), String> {
+    validate_fen(fen)?;
+    board.load_fen(fen.to_string());
+    Ok(())
+}
+
 pub fn generate_valid_moves(board: &mut Board) -> [Vec<usize>; 64] {
     const ARRAY_REPEAT_VALUE: Vec<usize> = Vec::new();
     let mut valid_moves = [ARRAY_REPEAT_VALUE; 64];
@@ -53,20 +237,290 @@ pub fn get_piece_color(piece: char) -> Color {
     }
 }
 
-pub fn move_piece(board: &mut Board, from: usize, to: usize) {
+pub const PROMOTION_PIECES: [net::PromotionPiece; 4] = [
+    net::PromotionPiece::Queen,
+    net::PromotionPiece::Rook,
+    net::PromotionPiece::Bishop,
+    net::PromotionPiece::Knight,
+];
+
+pub fn promotion_to_char(piece: net::PromotionPiece) -> char {
+    match piece {
+        net::PromotionPiece::Queen => 'Q',
+        net::PromotionPiece::Rook => 'R',
+        net::PromotionPiece::Bishop => 'B',
+        net::PromotionPiece::Knight => 'N',
+    }
+}
+
+pub fn char_to_promotion(c: char) -> Option<net::PromotionPiece> {
+    match c.to_ascii_uppercase() {
+        'Q' => Some(net::PromotionPiece::Queen),
+        'R' => Some(net::PromotionPiece::Rook),
+        'B' => Some(net::PromotionPiece::Bishop),
+        'N' => Some(net::PromotionPiece::Knight),
+        _ => None,
+    }
+}
+
+pub fn is_promotion(board_str: &str, from: usize, to: usize) -> bool {
+    let piece = board_str.chars().nth(from);
+    matches!(piece, Some('p') | Some('P')) && (to / 8 == 0 || to / 8 == 7)
+}
+
+// Pieces a move carries across the board, the rook comes along when castling
+pub fn moved_pieces(board_str: &str, from: usize, to: usize) -> Vec<(char, usize, usize)> {
+    let piece = board_str.chars().nth(from).unwrap_or(' ');
+    let mut moved = vec![(piece, from, to)];
+
+    if matches!(piece, 'k' | 'K') && from.abs_diff(to) == 2 {
+        let row = from / 8 * 8;
+        let (rook_from, rook_to) = if to > from {
+            (row + 7, to - 1)
+        } else {
+            (row, to + 1)
+        };
+
+        if let Some(rook) = board_str.chars().nth(rook_from) {
+            moved.push((rook, rook_from, rook_to));
+        }
+    }
+
+    moved
+}
+
+// The piece a move takes and where it stood, which is beside the target for en passant
+pub fn captured_piece(board_str: &str, from: usize, to: usize) -> Option<(char, usize)> {
+    let pieces: Vec<char> = board_str.chars().collect();
+    let piece = *pieces.get(from)?;
+    let target = *pieces.get(to)?;
+
+    if get_piece_color(target) != Color::None {
+        return Some((target, to));
+    }
+
+    if matches!(piece, 'p' | 'P') && from % 8 != to % 8 {
+        let square = from / 8 * 8 + to % 8;
+        return Some((*pieces.get(square)?, square));
+    }
+
+    None
+}
+
+// Squares the promotion dialog covers, starting at the promotion square and going towards the center
+pub fn promotion_square(to: usize, n: usize) -> usize {
+    let row = to / 8;
+    let col = to % 8;
+    let row = if row == 0 { n } else { row - n };
+    row * 8 + col
+}
+
+// Why a move can not be played in the position, checked before the board is touched
+pub fn validate_move(
+    board_str: &str,
+    valid_moves: &[Vec<usize>; 64],
+    turn: Color,
+    from: usize,
+    to: usize,
+    promotion: Option<net::PromotionPiece>,
+) -> Result<(), String> {
+    let piece = board_str.chars().nth(from).unwrap_or(' ');
+
+    if get_piece_color(piece) != turn {
+        return Err(format!("no {:?} piece on {}", turn, idx_to_str(from)));
+    }
+
+    if !valid_moves[from].contains(&to) {
+        return Err(format!(
+            "{}{} is not legal",
+            idx_to_str(from),
+            idx_to_str(to)
+        ));
+    }
+
+    match (is_promotion(board_str, from, to), promotion) {
+        (true, None) => Err("promotion without a piece".to_string()),
+        (false, Some(_)) => Err("promotion piece on a move that does not promote".to_string()),
+        _ => Ok(()),
+    }
+}
+
+pub fn move_piece(
+    board: &mut Board,
+    from: usize,
+    to: usize,
+    promotion: Option<net::PromotionPiece>,
+) {
     let from = idx_to_str(from);
     let to = idx_to_str(to);
 
-    let movi = format!("{}{}", from, to);
+    let mut movi = format!("{}{}", from, to);
+
+    if let Some(piece) = promotion {
+        movi.push(promotion_to_char(piece));
+    }
 
-    println!("Move: {}", movi);
+    log!(LogLevel::Debug, "Move: {}", movi);
 
-    let before_turn = board.start;
     make_move(board, movi);
+}
 
-    if before_turn == board.start {
-        let movi = format!("{}{}Q", from, to);
-        make_move(board, movi);
+// How a status is reported in acks, the protocol has no word for timeouts and resignations
+pub fn end_state(status: Status) -> Option<net::GameState> {
+    match status {
+        Status::Checkmate => Some(net::GameState::CheckMate),
+        Status::Stalemate
+        | Status::FiftyMoveRule
+        | Status::ThreefoldRepetition
+        | Status::DrawAgreed => Some(net::GameState::Draw),
+        Status::Active | Status::Timeout | Status::Resignation(_) | Status::IllegalMoves(_) => None,
+    }
+}
+
+pub fn same_end_state(a: Option<net::GameState>, b: Option<net::GameState>) -> bool {
+    matches!(
+        (a, b),
+        (None, None)
+            | (
+                Some(net::GameState::CheckMate),
+                Some(net::GameState::CheckMate)
+            )
+            | (Some(net::GameState::Draw), Some(net::GameState::Draw))
+    )
+}
+
+pub fn opposite_color(color: Color) -> Color {
+    match color {
+        Color::White => Color::Black,
+        Color::Black => Color::White,
+        Color::None => Color::None,
+    }
+}
+
+// Square index used by the board library, from a board string index
+pub fn board_square(idx: usize) -> i64 {
+    let x = idx % 8;
+    let y = 7 - idx / 8;
+    (x + y * 8) as i64
+}
+
+pub struct PlayedMove {
+    pub from: usize,
+    pub to: usize,
+    pub promotion: Option<net::PromotionPiece>,
+    pub san: String,
+}
+
+pub fn move_to_uci(m: &PlayedMove) -> String {
+    let mut uci = format!("{}{}", idx_to_str(m.from), idx_to_str(m.to));
+
+    if let Some(piece) = m.promotion {
+        uci.push(promotion_to_char(piece).to_ascii_lowercase());
+    }
+
+    uci
+}
+
+// SAN of a move without the check suffix, must be called before the move is made
+pub fn move_to_san(
+    board_str: &str,
+    valid_moves: &[Vec<usize>; 64],
+    from: usize,
+    to: usize,
+    promotion: Option<net::PromotionPiece>,
+) -> String {
+    let pieces: Vec<char> = board_str.chars().collect();
+    let piece = pieces[from].to_ascii_uppercase();
+    let capture = get_piece_color(pieces[to]) != Color::None;
+
+    if piece == 'K' && from.abs_diff(to) == 2 {
+        return if to > from { "O-O" } else { "O-O-O" }.to_string();
+    }
+
+    let mut san = String::new();
+
+    if piece == 'P' {
+        // FILE CHANGE WITHOUT A PIECE ON THE TARGET IS EN PASSANT
+        if capture || from % 8 != to % 8 {
+            san.push_str(&idx_to_str(from)[0..1]);
+            san.push('x');
+        }
+        san.push_str(&idx_to_str(to));
+
+        if let Some(piece) = promotion {
+            san.push('=');
+            san.push(promotion_to_char(piece));
+        }
+
+        return san;
+    }
+
+    san.push(piece);
+
+    // OTHER PIECES OF THE SAME KIND THAT CAN REACH THE SAME SQUARE
+    let others: Vec<usize> = (0..64)
+        .filter(|&o| o != from && pieces[o] == pieces[from] && valid_moves[o].contains(&to))
+        .collect();
+
+    if !others.is_empty() {
+        let square = idx_to_str(from);
+        if others.iter().all(|o| o % 8 != from % 8) {
+            san.push_str(&square[0..1]);
+        } else if others.iter().all(|o| o / 8 != from / 8) {
+            san.push_str(&square[1..2]);
+        } else {
+            san.push_str(&square);
+        }
+    }
+
+    if capture {
+        san.push('x');
+    }
+    san.push_str(&idx_to_str(to));
+
+    san
+}
+
+// Find the legal move matching a SAN string in the current position
+pub fn san_to_move(
+    board_str: &str,
+    valid_moves: &[Vec<usize>; 64],
+    san: &str,
+) -> Option<(usize, usize, Option<net::PromotionPiece>)> {
+    let san = san
+        .trim_end_matches(|c| "+#!?".contains(c))
+        .replace('0', "O");
+
+    for from in 0..64 {
+        for &to in valid_moves[from].iter() {
+            let promotions = if is_promotion(board_str, from, to) {
+                PROMOTION_PIECES.iter().map(|p| Some(*p)).collect()
+            } else {
+                vec![None]
+            };
+
+            for promotion in promotions {
+                if move_to_san(board_str, valid_moves, from, to, promotion) == san {
+                    return Some((from, to, promotion));
+                }
+            }
+        }
+    }
+
+    None
+}
+
+// "+" or "#" if the side to move is in check, must be called after the move is made
+pub fn check_suffix(board: &mut Board, board_str: &str, turn: Color) -> &'static str {
+    if is_over(board) == 1 {
+        return "#";
+    }
+
+    let king = if turn == Color::White { 'k' } else { 'K' };
+
+    match board_str.chars().position(|c| c == king) {
+        Some(idx) if in_check(board, board_square(idx)) => "+",
+        _ => "",
     }
 }
 
@@ -79,3 +533,222 @@ pub fn invert_boardstr(boardstr: String) -> String {
     }
     new_boardstr
 }
+
+#[cfg(test)]
+pub(crate) mod tests {
+    use super::*;
+
+    // Board string from the placement field of a FEN, with the library's lowercase white pieces
+    pub fn board(placement: &str) -> String {
+        placement
+            .split('/')
+            .flat_map(|rank| rank.chars())
+            .flat_map(|c| match c.to_digit(10) {
+                Some(n) => vec!['.'; n as usize],
+                None if c.is_ascii_uppercase() => vec![c.to_ascii_lowercase()],
+                None => vec![c.to_ascii_uppercase()],
+            })
+            .collect()
+    }
+
+    pub fn valid(moves: &[(&str, &str)]) -> [Vec<usize>; 64] {
+        let mut valid_moves: [Vec<usize>; 64] = std::array::from_fn(|_| Vec::new());
+        for (from, to) in moves {
+            valid_moves[str_to_idx(from)].push(str_to_idx(to));
+        }
+        valid_moves
+    }
+
+    pub fn play(board_str: &str, from: &str, to: &str) -> String {
+        let (from, to) = (str_to_idx(from), str_to_idx(to));
+        let mut pieces: Vec<char> = board_str.chars().collect();
+        let moved = moved_pieces(board_str, from, to);
+
+        if let Some((_, square)) = captured_piece(board_str, from, to) {
+            pieces[square] = '.';
+        }
+        for &(_, from, _) in moved.iter() {
+            pieces[from] = '.';
+        }
+        for &(piece, _, to) in moved.iter() {
+            pieces[to] = piece;
+        }
+
+        pieces.into_iter().collect()
+    }
+
+    pub fn find(
+        board_str: &str,
+        valid_moves: &[Vec<usize>; 64],
+        san: &str,
+    ) -> Option<(String, String, Option<char>)> {
+        san_to_move(board_str, valid_moves, san)
+            .map(|(from, to, p)| (idx_to_str(from), idx_to_str(to), p.map(promotion_to_char)))
+    }
+
+    fn found(
+        from: &str,
+        to: &str,
+        promotion: Option<char>,
+    ) -> Option<(String, String, Option<char>)> {
+        Some((from.to_string(), to.to_string(), promotion))
+    }
+
+    fn san(board_str: &str, valid_moves: &[Vec<usize>; 64], from: &str, to: &str) -> String {
+        move_to_san(
+            board_str,
+            valid_moves,
+            str_to_idx(from),
+            str_to_idx(to),
+            None,
+        )
+    }
+
+    #[test]
+    fn san_disambiguates_by_file_rank_or_square() {
+        let b = board("8/8/8/8/8/8/8/1N3N2");
+        let v = valid(&[("b1", "d2"), ("f1", "d2"), ("b1", "c3")]);
+        assert_eq!(san(&b, &v, "b1", "d2"), "Nbd2");
+        assert_eq!(san(&b, &v, "f1", "d2"), "Nfd2");
+        assert_eq!(san(&b, &v, "b1", "c3"), "Nc3");
+        assert_eq!(find(&b, &v, "Nfd2"), found("f1", "d2", None));
+
+        let b = board("8/8/8/r7/8/8/8/r7");
+        let v = valid(&[("a1", "a3"), ("a5", "a3")]);
+        assert_eq!(san(&b, &v, "a1", "a3"), "R1a3");
+        assert_eq!(san(&b, &v, "a5", "a3"), "R5a3");
+        assert_eq!(find(&b, &v, "R5a3"), found("a5", "a3", None));
+
+        let b = board("8/8/8/8/8/Q7/8/Q1Q5");
+        let v = valid(&[("a1", "b2"), ("a3", "b2"), ("c1", "b2")]);
+        assert_eq!(san(&b, &v, "a1", "b2"), "Qa1b2");
+        assert_eq!(san(&b, &v, "a3", "b2"), "Q3b2");
+        assert_eq!(san(&b, &v, "c1", "b2"), "Qcb2");
+        assert_eq!(find(&b, &v, "Qa1b2"), found("a1", "b2", None));
+        assert_eq!(find(&b, &v, "Qb2"), None);
+    }
+
+    #[test]
+    fn en_passant_takes_the_pawn_beside_the_target() {
+        let b = board("8/8/8/3pP3/8/8/8/8");
+        let v = valid(&[("e5", "d6"), ("e5", "e6")]);
+
+        assert_eq!(san(&b, &v, "e5", "d6"), "exd6");
+        assert_eq!(san(&b, &v, "e5", "e6"), "e6");
+        assert_eq!(
+            captured_piece(&b, str_to_idx("e5"), str_to_idx("d6")),
+            Some(('P', str_to_idx("d5")))
+        );
+        assert_eq!(find(&b, &v, "exd6"), found("e5", "d6", None));
+        assert_eq!(play(&b, "e5", "d6"), board("8/8/3P4/8/8/8/8/8"));
+    }
+
+    #[test]
+    fn promotions_name_the_piece() {
+        let b = board("r7/1P6/8/8/8/8/8/8");
+        let v = valid(&[("b7", "b8"), ("b7", "a8")]);
+        let (b7, b8, a8) = (str_to_idx("b7"), str_to_idx("b8"), str_to_idx("a8"));
+
+        assert!(is_promotion(&b, b7, b8));
+        assert!(!is_promotion(&b, a8, b8));
+        assert_eq!(
+            move_to_san(&b, &v, b7, b8, Some(net::PromotionPiece::Knight)),
+            "b8=N"
+        );
+        assert_eq!(
+            move_to_san(&b, &v, b7, a8, Some(net::PromotionPiece::Queen)),
+            "bxa8=Q"
+        );
+        assert_eq!(find(&b, &v, "bxa8=Q+"), found("b7", "a8", Some('Q')));
+        assert_eq!(find(&b, &v, "b8=R"), found("b7", "b8", Some('R')));
+        assert_eq!(find(&b, &v, "b8"), None);
+
+        let m = PlayedMove {
+            from: b7,
+            to: a8,
+            promotion: Some(net::PromotionPiece::Queen),
+            san: "bxa8=Q".to_string(),
+        };
+        assert_eq!(move_to_uci(&m), "b7a8q");
+    }
+
+    #[test]
+    fn castling_moves_the_rook_along() {
+        let b = board("r3k2r/8/8/8/8/8/8/R3K2R");
+        let v = valid(&[("e1", "g1"), ("e1", "c1")]);
+
+        assert_eq!(san(&b, &v, "e1", "g1"), "O-O");
+        assert_eq!(san(&b, &v, "e1", "c1"), "O-O-O");
+        assert_eq!(san(&b, &valid(&[("e8", "c8")]), "e8", "c8"), "O-O-O");
+        assert_eq!(find(&b, &v, "0-0-0"), found("e1", "c1", None));
+        assert_eq!(
+            moved_pieces(&b, str_to_idx("e8"), str_to_idx("c8")),
+            vec![
+                ('K', str_to_idx("e8"), str_to_idx("c8")),
+                ('R', str_to_idx("a8"), str_to_idx("d8"))
+            ]
+        );
+        assert_eq!(play(&b, "e1", "g1"), board("r3k2r/8/8/8/8/8/8/R4RK1"));
+    }
+
+    // Plays a line where each move is the only legal one, returning the final board and the moves
+    fn play_line(start: &str, line: &[(&str, &str)]) -> (String, Vec<PlayedMove>) {
+        let mut b = start.to_string();
+        let mut moves = Vec::new();
+
+        for &(from, to) in line {
+            moves.push(PlayedMove {
+                from: str_to_idx(from),
+                to: str_to_idx(to),
+                promotion: None,
+                san: san(&b, &valid(&[(from, to)]), from, to),
+            });
+            b = play(&b, from, to);
+        }
+
+        (b, moves)
+    }
+
+    #[test]
+    fn position_fen_follows_rights_and_counters() {
+        let start = board("rnbqkbnr/pppppppp/8/8/8/8/PPPPPPPP/RNBQKBNR");
+
+        let (b, moves) = play_line(&start, &[("e2", "e4")]);
+        assert_eq!(
+            position_fen(&b, Color::Black, None, &moves),
+            "rnbqkbnr/pppppppp/8/8/4P3/8/PPPP1PPP/RNBQKBNR b KQkq e3 0 1"
+        );
+
+        let line = [
+            ("e2", "e4"),
+            ("e7", "e5"),
+            ("g1", "f3"),
+            ("b8", "c6"),
+            ("h1", "g1"),
+        ];
+        let (b, moves) = play_line(&start, &line);
+        assert_eq!(
+            position_fen(&b, Color::Black, None, &moves),
+            "r1bqkbnr/pppp1ppp/2n5/4p3/4P3/5N2/PPPP1PPP/RNBQKBR1 b Qkq - 3 3"
+        );
+    }
+
+    #[test]
+    fn position_fen_continues_from_a_start_fen() {
+        let fen = "4k3/8/8/8/8/8/8/4K2R b K - 7 40";
+        let start = board("4k3/8/8/8/8/8/8/4K2R");
+        assert_eq!(position_fen(&start, Color::Black, Some(fen), &[]), fen);
+
+        let (b, moves) = play_line(&start, &[("e8", "d7")]);
+        assert_eq!(
+            position_fen(&b, Color::White, Some(fen), &moves),
+            "8/3k4/8/8/8/8/8/4K2R w K - 8 41"
+        );
+
+        let (b, moves) = play_line(&start, &[("e8", "d7"), ("e1", "f1")]);
+        assert_eq!(
+            position_fen(&b, Color::Black, Some(fen), &moves),
+            "8/3k4/8/8/8/8/8/5K1R b - - 9 41"
+        );
+    }
+}
@@ -0,0 +1,329 @@
+use crate::*;
+use std::fmt;
+
+pub const HELP: &str = "\
+Usage: liamt-chess-gui <command> [options]
+
+Commands:
+  host                    Wait for an opponent to join on --port
+  join <address>          Join a hosted game, address is host or host:port
+  local                   Both sides play from this window
+  ai                      Play against the built-in computer
+  engine <path>           Play against a UCI engine
+  replay <file.pgn>       Step through saved games
+  analyze                 Move both sides freely, no clock and no saving
+
+Options:
+  --port <port>           Port to host on or join, default 8080
+  --name <name>           Name shown to the opponent
+  --color <color>         white, black or random
+  --time <seconds>        Time per side
+  --inc <seconds>         Increment per move
+  --fen <fen>             Start from this position
+  --window <w>x<h>        Window size, default 1200x1000
+  --theme <name>          Board colors, one of classic, brown, green, blue
+  --pieces <name>         Piece set, a directory of PNG or SVG files under resources/
+  --log-level <level>     error, warn, info or debug, default info
+  --illegal <policy>      After repeated illegal moves: warn, ignore or end
+  --depth <n>             Search depth for ai and engine
+  --think <ms>            Time the built-in computer thinks per move
+  --movetime <ms>         Time the engine thinks per move
+  --option <name>=<value> UCI option for the engine, can be repeated
+  -h, --help              Show this help
+
+Options override the settings file, press Ctrl+, in game to edit it
+Press F in game to flip the board";
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum Mode {
+    Host,
+    Join(String),
+    Local,
+    Ai,
+    Engine(String),
+    Replay(String),
+    Analyze,
+}
+
+pub struct Cli {
+    pub mode: Mode,
+    pub port: Option<u16>,
+    pub name: Option<String>,
+    pub color: Option<ColorPreference>,
+    pub time: Option<u64>,
+    pub inc: Option<u64>,
+    pub fen: Option<String>,
+    pub window: Option<(f32, f32)>,
+    pub theme: Option<String>,
+    pub pieces: Option<String>,
+    pub log_level: LogLevel,
+    pub illegal: IllegalMovePolicy,
+    pub depth: Option<u64>,
+    pub think: u64,
+    pub movetime: Option<u64>,
+    pub options: Vec<(String, String)>,
+}
+
+#[derive(Debug)]
+pub enum CliError {
+    Help,
+    Invalid(String),
+}
+
+impl fmt::Display for CliError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            CliError::Help => write!(f, "{}", HELP),
+            CliError::Invalid(e) => write!(f, "{}\n\nRun with --help for usage", e),
+        }
+    }
+}
+
+fn invalid<T>(msg: String) -> Result<T, CliError> {
+    Err(CliError::Invalid(msg))
+}
+
+// Which commands a flag makes sense for
+fn flag_modes(flag: &str) -> Option<&'static [&'static str]> {
+    let modes: &[&str] = match flag {
+        "--port" => &["host", "join"],
+        "--name" => &["host", "join"],
+        "--color" => &["host", "join", "ai", "engine"],
+        "--time" | "--inc" => &["host", "join", "local"],
+        "--fen" => &["host", "join", "local", "ai", "engine", "analyze"],
+        "--illegal" => &["host", "join", "ai", "engine"],
+        "--depth" => &["ai", "engine"],
+        "--think" => &["ai"],
+        "--movetime" | "--option" => &["engine"],
+        "--window" | "--theme" | "--pieces" | "--log-level" => {
+            &["host", "join", "local", "ai", "engine", "replay", "analyze"]
+        }
+        _ => return None,
+    };
+
+    Some(modes)
+}
+
+fn parse_number(flag: &str, value: &str) -> Result<u64, CliError> {
+    match value.parse() {
+        Ok(n) => Ok(n),
+        Err(_) => invalid(format!("Invalid {} {}, must be a number", flag, value)),
+    }
+}
+
+// Parse everything after the program name
+pub fn parse_args(args: &[String]) -> Result<Cli, CliError> {
+    let mut positional = Vec::new();
+    let mut flags = Vec::new();
+
+    let mut args = args.iter();
+    while let Some(arg) = args.next() {
+        if arg == "-h" || arg == "--help" {
+            return Err(CliError::Help);
+        }
+
+        if arg.starts_with("--") {
+            if flag_modes(arg).is_none() {
+                return invalid(format!("Unknown option {}", arg));
+            }
+
+            match args.next() {
+                Some(value) => flags.push((arg.as_str(), value.as_str())),
+                None => return invalid(format!("Missing value after {}", arg)),
+            }
+        } else {
+            positional.push(arg.as_str());
+        }
+    }
+
+    let Some((&command, rest)) = positional.split_first() else {
+        return Err(CliError::Help);
+    };
+
+    let argument = |name: &str| match rest {
+        [value] => Ok(value.to_string()),
+        [] => invalid(format!("{} needs {}", command, name)),
+        _ => invalid(format!("Too many arguments for {}", command)),
+    };
+
+    let mode = match command {
+        "host" | "local" | "ai" | "analyze" if !rest.is_empty() => {
+            return invalid(format!("Too many arguments for {}", command));
+        }
+        "host" => Mode::Host,
+        "local" => Mode::Local,
+        "ai" => Mode::Ai,
+        "analyze" => Mode::Analyze,
+        "join" => Mode::Join(argument("an address")?),
+        "engine" => Mode::Engine(argument("the path to an engine")?),
+        "replay" => Mode::Replay(argument("a PGN file")?),
+        _ => return invalid(format!("Unknown command {}", command)),
+    };
+
+    let mut cli = Cli {
+        mode,
+        port: None,
+        name: None,
+        color: None,
+        time: None,
+        inc: None,
+        fen: None,
+        window: None,
+        theme: None,
+        pieces: None,
+        log_level: LogLevel::Info,
+        illegal: IllegalMovePolicy::Warn,
+        depth: None,
+        think: 2000,
+        movetime: None,
+        options: Vec::new(),
+    };
+
+    for (flag, value) in flags {
+        if !flag_modes(flag).unwrap_or(&[]).contains(&command) {
+            return invalid(format!("{} can not be used with {}", flag, command));
+        }
+
+        match flag {
+            "--port" => match value.parse() {
+                Ok(port) => cli.port = Some(port),
+                Err(_) => return invalid(format!("Invalid --port {}", value)),
+            },
+            "--name" => cli.name = Some(value.to_string()),
+            "--color" => match value.parse() {
+                Ok(color) => cli.color = Some(color),
+                Err(e) => return invalid(format!("Invalid --color: {}", e)),
+            },
+            "--time" => match parse_number(flag, value)? {
+                0 => return invalid("Invalid --time, must be positive".to_string()),
+                time => cli.time = Some(time),
+            },
+            "--inc" => cli.inc = Some(parse_number(flag, value)?),
+            "--fen" => match validate_fen(value) {
+                Ok(()) => cli.fen = Some(value.to_string()),
+                Err(e) => return invalid(format!("Invalid FEN: {}", e)),
+            },
+            "--window" => match parse_window(value) {
+                Some(window) => cli.window = Some(window),
+                None => {
+                    return invalid(format!(
+                        "Invalid --window {}, must be <width>x<height> of at least {}x{}",
+                        value, MIN_WINDOW.0, MIN_WINDOW.1
+                    ))
+                }
+            },
+            "--theme" => match find_theme(value) {
+                Some(_) => cli.theme = Some(value.to_string()),
+                None => return invalid(format!("Unknown theme {}", value)),
+            },
+            "--pieces" => cli.pieces = Some(value.to_string()),
+            "--log-level" => match value.parse() {
+                Ok(level) => cli.log_level = level,
+                Err(e) => return invalid(format!("Invalid --log-level: {}", e)),
+            },
+            "--illegal" => match value.parse() {
+                Ok(policy) => cli.illegal = policy,
+                Err(e) => return invalid(format!("Invalid --illegal: {}", e)),
+            },
+            "--depth" => cli.depth = Some(parse_number(flag, value)?),
+            "--think" => cli.think = parse_number(flag, value)?,
+            "--movetime" => cli.movetime = Some(parse_number(flag, value)?),
+            "--option" => match value.split_once('=') {
+                Some((name, value)) => cli.options.push((name.to_string(), value.to_string())),
+                None => return invalid("Invalid --option, must be <name>=<value>".to_string()),
+            },
+            _ => unreachable!("flag_modes knows every flag"),
+        }
+    }
+
+    if cli.inc.is_some() && cli.time.is_none() {
+        return invalid("--inc needs --time".to_string());
+    }
+
+    Ok(cli)
+}
+
+impl Cli {
+    // Address to listen on or connect to
+    pub fn address(&self, port: u16) -> String {
+        match &self.mode {
+            Mode::Join(addr) if addr.contains(':') => addr.clone(),
+            Mode::Join(host) => format!("{}:{}", host, port),
+            _ => format!("0.0.0.0:{}", port),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn parse(line: &str) -> Result<Cli, CliError> {
+        let args: Vec<String> = line.split_whitespace().map(String::from).collect();
+        parse_args(&args)
+    }
+
+    fn error(line: &str) -> String {
+        match parse(line) {
+            Err(CliError::Invalid(e)) => e,
+            Err(CliError::Help) => panic!("{} asked for help", line),
+            Ok(_) => panic!("{} was accepted", line),
+        }
+    }
+
+    #[test]
+    fn unknown_commands_and_flags() {
+        assert_eq!(error("play"), "Unknown command play");
+        assert_eq!(error("host --colour white"), "Unknown option --colour");
+        assert_eq!(error("host extra"), "Too many arguments for host");
+        assert_eq!(error("join"), "join needs an address");
+    }
+
+    #[test]
+    fn flags_only_go_with_their_commands() {
+        assert_eq!(
+            error("host --think 100"),
+            "--think can not be used with host"
+        );
+        assert_eq!(
+            error("replay game.pgn --fen x"),
+            "--fen can not be used with replay"
+        );
+        assert_eq!(parse("ai --think 100").map(|cli| cli.think).ok(), Some(100));
+    }
+
+    #[test]
+    fn flags_need_valid_values() {
+        assert_eq!(error("host --port"), "Missing value after --port");
+        assert_eq!(error("host --port http"), "Invalid --port http");
+        assert_eq!(error("local --time 0"), "Invalid --time, must be positive");
+        assert_eq!(error("local --inc 5"), "--inc needs --time");
+
+        let cli = parse("local --inc 5 --time 300").ok().unwrap();
+        assert_eq!((cli.time, cli.inc), (Some(300), Some(5)));
+    }
+
+    #[test]
+    fn join_takes_a_host_with_or_without_a_port() {
+        let cli = parse("join example.org").ok().unwrap();
+        assert_eq!(cli.mode, Mode::Join("example.org".to_string()));
+        assert_eq!(cli.address(8080), "example.org:8080");
+
+        let cli = parse("join example.org:9000 --port 1234").ok().unwrap();
+        assert_eq!(cli.address(1234), "example.org:9000");
+
+        let cli = parse("host").ok().unwrap();
+        assert_eq!(cli.address(8080), "0.0.0.0:8080");
+    }
+
+    #[test]
+    fn help_instead_of_a_game() {
+        assert!(matches!(parse("-h"), Err(CliError::Help)));
+        assert!(matches!(
+            parse("host --port 9000 --help"),
+            Err(CliError::Help)
+        ));
+        assert!(matches!(parse("-h --port"), Err(CliError::Help)));
+        assert!(matches!(parse(""), Err(CliError::Help)));
+    }
+}
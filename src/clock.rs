@@ -0,0 +1,57 @@
+use crate::*;
+
+pub struct Clock {
+    pub white: Duration,
+    pub black: Duration,
+    pub inc: Duration,
+}
+
+impl Clock {
+    pub fn new(time: u64, inc: u64) -> Self {
+        Self {
+            white: Duration::from_secs(time),
+            black: Duration::from_secs(time),
+            inc: Duration::from_secs(inc),
+        }
+    }
+
+    pub fn remaining(&self, color: Color) -> Duration {
+        match color {
+            Color::White => self.white,
+            _ => self.black,
+        }
+    }
+
+    // Count down the clock of the side to move
+    pub fn tick(&mut self, turn: Color, dt: Duration) {
+        match turn {
+            Color::White => self.white = self.white.saturating_sub(dt),
+            Color::Black => self.black = self.black.saturating_sub(dt),
+            Color::None => (),
+        }
+    }
+
+    pub fn add_increment(&mut self, color: Color) {
+        match color {
+            Color::White => self.white += self.inc,
+            Color::Black => self.black += self.inc,
+            Color::None => (),
+        }
+    }
+
+    // Returns the color whose time has run out, if any
+    pub fn flagged(&self) -> Option<Color> {
+        if self.white.is_zero() {
+            Some(Color::White)
+        } else if self.black.is_zero() {
+            Some(Color::Black)
+        } else {
+            None
+        }
+    }
+}
+
+pub fn format_time(time: Duration) -> String {
+    let secs = time.as_secs();
+    format!("{:02}:{:02}", secs / 60, secs % 60)
+}
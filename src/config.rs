@@ -0,0 +1,347 @@
+use crate::*;
+use std::path::PathBuf;
+use std::time::{SystemTime, UNIX_EPOCH};
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum ColorPreference {
+    White,
+    Black,
+    Random,
+}
+
+impl FromStr for ColorPreference {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "white" => Ok(ColorPreference::White),
+            "black" => Ok(ColorPreference::Black),
+            "random" => Ok(ColorPreference::Random),
+            _ => Err(format!(
+                "unknown color {}, must be white, black or random",
+                s
+            )),
+        }
+    }
+}
+
+impl ColorPreference {
+    pub fn name(self) -> &'static str {
+        match self {
+            ColorPreference::White => "white",
+            ColorPreference::Black => "black",
+            ColorPreference::Random => "random",
+        }
+    }
+
+    // Whether to ask for white, random picks by the clock
+    pub fn is_white(self) -> bool {
+        match self {
+            ColorPreference::White => true,
+            ColorPreference::Black => false,
+            ColorPreference::Random => SystemTime::now()
+                .duration_since(UNIX_EPOCH)
+                .map(|d| d.subsec_nanos() % 2 == 0)
+                .unwrap_or(true),
+        }
+    }
+}
+
+#[derive(Clone, Debug, PartialEq)]
+pub struct Config {
+    pub name: Option<String>,
+    // NONE ASKS FOR WHITE AS A CLIENT AND GIVES THE CLIENT ITS CHOICE AS A SERVER
+    pub color: Option<ColorPreference>,
+    pub port: u16,
+    pub window: (f32, f32),
+    // SPACE AROUND THE BOARD FOR LABELS, BUTTONS AND CLOCKS
+    pub margin: f32,
+    pub theme: String,
+    // DIRECTORY UNDER resources/ WITH THE PIECE IMAGES
+    pub pieces: String,
+    // SELECTION, VALID MOVES AND HIGHLIGHTED TEXT
+    pub accent: (u8, u8, u8),
+    // MILLISECONDS A MOVE TAKES TO SLIDE INTO PLACE, 0 TURNS IT OFF
+    pub animation: u64,
+}
+
+impl Default for Config {
+    fn default() -> Self {
+        Config {
+            name: None,
+            color: None,
+            port: 8080,
+            window: (1200.0, 1000.0),
+            margin: 100.0,
+            theme: "classic".to_string(),
+            pieces: DEFAULT_PIECES.to_string(),
+            accent: (199, 38, 239),
+            animation: 200,
+        }
+    }
+}
+
+impl Config {
+    pub fn accent_color(&self) -> graphics::Color {
+        graphics::Color::from_rgb(self.accent.0, self.accent.1, self.accent.2)
+    }
+}
+
+// Whether to ask for white, without a preference we ask for white like before there were preferences
+pub fn wants_white(color: Option<ColorPreference>) -> bool {
+    color.is_none_or(|color| color.is_white())
+}
+
+// Smallest window that fits the board, its labels and the move list
+pub const MIN_WINDOW: (f32, f32) = (600.0, 400.0);
+
+// "<width>x<height>", at least MIN_WINDOW
+pub fn parse_window(value: &str) -> Option<(f32, f32)> {
+    let (w, h) = value.split_once('x')?;
+    let (w, h) = (w.parse::<u32>().ok()? as f32, h.parse::<u32>().ok()? as f32);
+
+    (w >= MIN_WINDOW.0 && h >= MIN_WINDOW.1).then_some((w, h))
+}
+
+pub fn clamp_window(window: (f32, f32)) -> (f32, f32) {
+    (window.0.max(MIN_WINDOW.0), window.1.max(MIN_WINDOW.1))
+}
+
+// Smallest and largest margin, for the config file and the settings screen
+pub const MARGIN_RANGE: (f32, f32) = (20.0, 200.0);
+
+// "#rrggbb"
+pub fn parse_hex_color(value: &str) -> Option<(u8, u8, u8)> {
+    let hex = value.strip_prefix('#')?;
+    if hex.len() != 6 {
+        return None;
+    }
+
+    let channel = |i: usize| u8::from_str_radix(hex.get(i..i + 2)?, 16).ok();
+    Some((channel(0)?, channel(2)?, channel(4)?))
+}
+
+// $XDG_CONFIG_HOME/liamt-chess/config.toml, falling back to ~/.config
+pub fn config_path() -> Option<PathBuf> {
+    let dir = match std::env::var_os("XDG_CONFIG_HOME") {
+        Some(dir) if !dir.is_empty() => PathBuf::from(dir),
+        _ => PathBuf::from(std::env::var_os("HOME")?).join(".config"),
+    };
+
+    Some(dir.join("liamt-chess").join("config.toml"))
+}
+
+// A missing file gives the defaults, bad lines are reported and skipped
+pub fn load_config() -> Config {
+    let text = config_path().and_then(|path| std::fs::read_to_string(path).ok());
+
+    match text {
+        Some(text) => parse_config(&text),
+        None => Config::default(),
+    }
+}
+
+pub fn save_config(config: &Config) -> Result<(), String> {
+    let path = config_path().ok_or("no config directory, set HOME or XDG_CONFIG_HOME")?;
+
+    if let Some(dir) = path.parent() {
+        std::fs::create_dir_all(dir).map_err(|e| e.to_string())?;
+    }
+
+    std::fs::write(&path, write_config(config)).map_err(|e| e.to_string())?;
+    log!(LogLevel::Info, "Saved settings to {}", path.display());
+    Ok(())
+}
+
+// TOML basic string, quotes and backslashes are the only escapes the values need
+fn quote(value: &str) -> String {
+    format!("\"{}\"", value.replace('\\', "\\\\").replace('"', "\\\""))
+}
+
+// Undo quote, unquoted values are taken as they are
+fn unquote(value: &str) -> String {
+    let Some(inner) = value
+        .strip_prefix('"')
+        .and_then(|value| value.strip_suffix('"'))
+    else {
+        return value.to_string();
+    };
+
+    let mut text = String::new();
+    let mut chars = inner.chars();
+    while let Some(c) = chars.next() {
+        match c {
+            '\\' => text.extend(chars.next()),
+            c => text.push(c),
+        }
+    }
+
+    text
+}
+
+// The settings file after editing, only what changed between before and after goes in,
+// so options given on the command line for this run stay out of it
+pub fn changed_settings(file: &Config, before: &Config, after: &Config) -> Config {
+    let mut config = file.clone();
+
+    macro_rules! keep_changes {
+        ($($field:ident),*) => {
+            $(
+                if after.$field != before.$field {
+                    config.$field = after.$field.clone();
+                }
+            )*
+        };
+    }
+
+    keep_changes!(name, color, port, window, margin, theme, pieces, accent, animation);
+    config
+}
+
+// `key = value` lines, values may be quoted, lines starting with # are comments
+pub fn parse_config(text: &str) -> Config {
+    let mut config = Config::default();
+
+    for line in text.lines() {
+        let line = line.trim();
+        if line.is_empty() || line.starts_with('#') {
+            continue;
+        }
+
+        let Some((key, value)) = line.split_once('=') else {
+            log!(LogLevel::Warn, "Ignoring config line without '=': {}", line);
+            continue;
+        };
+
+        let key = key.trim();
+        let value = unquote(value.trim());
+        let value = value.as_str();
+
+        let valid = match key {
+            "name" => {
+                config.name = Some(value.to_string());
+                true
+            }
+            "color" => value
+                .parse()
+                .map(|color| config.color = Some(color))
+                .is_ok(),
+            "port" => value.parse().map(|port| config.port = port).is_ok(),
+            "window" => parse_window(value)
+                .map(|window| config.window = window)
+                .is_some(),
+            "margin" => value
+                .parse::<f32>()
+                .ok()
+                .filter(|margin| (MARGIN_RANGE.0..=MARGIN_RANGE.1).contains(margin))
+                .map(|margin| config.margin = margin)
+                .is_some(),
+            "theme" => find_theme(value)
+                .map(|theme| config.theme = theme.name.to_string())
+                .is_some(),
+            "pieces" => {
+                config.pieces = value.to_string();
+                true
+            }
+            "accent" => parse_hex_color(value)
+                .map(|accent| config.accent = accent)
+                .is_some(),
+            "animation" => value
+                .parse::<u64>()
+                .ok()
+                .filter(|ms| *ms <= 2000)
+                .map(|ms| config.animation = ms)
+                .is_some(),
+            _ => {
+                log!(LogLevel::Warn, "Ignoring unknown config key {}", key);
+                true
+            }
+        };
+
+        if !valid {
+            log!(LogLevel::Warn, "Ignoring invalid config {}: {}", key, value);
+        }
+    }
+
+    config
+}
+
+pub fn write_config(config: &Config) -> String {
+    let mut text = String::new();
+
+    if let Some(name) = &config.name {
+        text += &format!("name = {}\n", quote(name));
+    }
+
+    if let Some(color) = config.color {
+        text += &format!("color = {}\n", quote(color.name()));
+    }
+
+    let window = format!("{}x{}", config.window.0, config.window.1);
+    let (r, g, b) = config.accent;
+    let accent = format!("#{:02x}{:02x}{:02x}", r, g, b);
+
+    text += &format!("port = {}\n", config.port);
+    text += &format!("window = {}\n", quote(&window));
+    text += &format!("margin = {}\n", config.margin);
+    text += &format!("theme = {}\n", quote(&config.theme));
+    text += &format!("pieces = {}\n", quote(&config.pieces));
+    text += &format!("accent = {}\n", quote(&accent));
+    text += &format!("animation = {}\n", config.animation);
+
+    text
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn names_with_quotes_survive_a_save() {
+        let config = Config {
+            name: Some(r#"The "best" \ player"#.to_string()),
+            color: Some(ColorPreference::Black),
+            ..Config::default()
+        };
+
+        assert_eq!(parse_config(&write_config(&config)), config);
+    }
+
+    #[test]
+    fn unquoted_and_commented_lines() {
+        let config = parse_config("# comment\nport = 9000\ntheme = green\nname = Bob\n");
+
+        assert_eq!(config.port, 9000);
+        assert_eq!(config.theme, "green");
+        assert_eq!(config.name.as_deref(), Some("Bob"));
+        assert_eq!(config.color, None);
+    }
+
+    #[test]
+    fn command_line_overrides_stay_out_of_the_file() {
+        let file = Config::default();
+        let before = Config {
+            port: 9000,
+            theme: "blue".to_string(),
+            ..file.clone()
+        };
+        let after = Config {
+            margin: 50.0,
+            ..before.clone()
+        };
+
+        let saved = changed_settings(&file, &before, &after);
+        assert_eq!(saved.port, file.port);
+        assert_eq!(saved.theme, file.theme);
+        assert_eq!(saved.margin, 50.0);
+    }
+
+    #[test]
+    fn margins_outside_the_range_are_ignored() {
+        let default = Config::default().margin;
+
+        assert_eq!(parse_config("margin = 300").margin, default);
+        assert_eq!(parse_config("margin = 0").margin, default);
+        assert_eq!(parse_config("margin = 200").margin, MARGIN_RANGE.1);
+    }
+}
@@ -1,27 +1,474 @@
 use crate::*;
+use ggez::GameError;
+use resvg::{tiny_skia, usvg};
+use std::io::Read;
 
-pub fn load_piece_images(ctx: &Context) -> Vec<(String, graphics::Image)> {
-    let pieces = vec![
-        ("p", "/wP.png"),
-        ("r", "/wR.png"),
-        ("n", "/wN.png"),
-        ("b", "/wB.png"),
-        ("q", "/wQ.png"),
-        ("k", "/wK.png"),
-        ("P", "/bP.png"),
-        ("R", "/bR.png"),
-        ("N", "/bN.png"),
-        ("B", "/bB.png"),
-        ("Q", "/bQ.png"),
-        ("K", "/bK.png"),
-    ];
+// Board characters and the file names of their images, without the extension
+const PIECE_FILES: [(&str, &str); 12] = [
+    ("p", "wP"),
+    ("r", "wR"),
+    ("n", "wN"),
+    ("b", "wB"),
+    ("q", "wQ"),
+    ("k", "wK"),
+    ("P", "bP"),
+    ("R", "bR"),
+    ("N", "bN"),
+    ("B", "bB"),
+    ("Q", "bQ"),
+    ("K", "bK"),
+];
 
+// SVG sets are drawn at the size they were loaded at, so they are loaded again when the squares change size
+pub fn is_svg_set(ctx: &Context, set: &str) -> bool {
+    PIECE_FILES
+        .iter()
+        .any(|(_, file)| ctx.fs.exists(piece_path(set, &format!("{}.svg", file))))
+}
+
+// Pieces missing from the set come from the default set, pieces missing from both are not drawn
+pub fn load_piece_images(ctx: &Context, set: &str, size: u32) -> Vec<(String, graphics::Image)> {
     let mut piece_images = Vec::new();
 
-    for (piece, path) in pieces {
-        let img = graphics::Image::from_path(ctx, path).unwrap();
-        piece_images.push((String::from_str(piece).unwrap(), img));
+    for (piece, file) in PIECE_FILES {
+        let img = load_piece_image(ctx, set, file, size).or_else(|e| {
+            if set == DEFAULT_PIECES {
+                return Err(e);
+            }
+
+            log!(LogLevel::Warn, "Piece set {} has no {}: {}", set, file, e);
+            load_piece_image(ctx, DEFAULT_PIECES, file, size)
+        });
+
+        match img {
+            Ok(img) => piece_images.push((piece.to_string(), img)),
+            Err(e) => log!(LogLevel::Error, "Could not load {}: {}", file, e),
+        }
     }
 
     piece_images
 }
+
+// An SVG is preferred over a PNG with the same name
+fn load_piece_image(
+    ctx: &Context,
+    set: &str,
+    file: &str,
+    size: u32,
+) -> GameResult<graphics::Image> {
+    let svg = piece_path(set, &format!("{}.svg", file));
+
+    if ctx.fs.exists(&svg) {
+        rasterize_svg(ctx, &svg, size)
+    } else {
+        graphics::Image::from_path(ctx, piece_path(set, &format!("{}.png", file)))
+    }
+}
+
+// Render an SVG into a size by size image, keeping its aspect ratio
+fn rasterize_svg(ctx: &Context, path: &str, size: u32) -> GameResult<graphics::Image> {
+    let mut data = Vec::new();
+    ctx.fs.open(path)?.read_to_end(&mut data)?;
+
+    let tree = usvg::Tree::from_data(&data, &usvg::Options::default())
+        .map_err(|e| GameError::ResourceLoadError(format!("{}: {}", path, e)))?;
+
+    let size = size.max(1);
+    let mut pixmap = tiny_skia::Pixmap::new(size, size)
+        .ok_or_else(|| GameError::ResourceLoadError(format!("{}: no pixmap", path)))?;
+
+    let svg_size = tree.size();
+    let scale = size as f32 / svg_size.width().max(svg_size.height());
+    let dx = (size as f32 - svg_size.width() * scale) / 2.0;
+    let dy = (size as f32 - svg_size.height() * scale) / 2.0;
+    let transform = tiny_skia::Transform::from_scale(scale, scale).post_translate(dx, dy);
+    resvg::render(&tree, transform, &mut pixmap.as_mut());
+
+    // tiny-skia keeps premultiplied alpha, images want it straight
+    let pixels: Vec<u8> = pixmap
+        .pixels()
+        .iter()
+        .flat_map(|pixel| {
+            let color = pixel.demultiply();
+            [color.red(), color.green(), color.blue(), color.alpha()]
+        })
+        .collect();
+
+    Ok(graphics::Image::from_pixels(
+        ctx,
+        &pixels,
+        graphics::ImageFormat::Rgba8UnormSrgb,
+        size,
+        size,
+    ))
+}
+
+// Width of the move list right of the board
+pub const PANEL_WIDTH: f32 = 200.0;
+
+// Height of one line in the move list
+const MOVE_ROW_HEIGHT: f32 = 26.0;
+
+// Where everything goes on screen, the board is centered beside the move list and fills the window minus the margin
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct Layout {
+    pub tile: f32,
+    pub left: f32,
+    pub top: f32,
+    pub margin: f32,
+    // Black at the bottom
+    pub flipped: bool,
+}
+
+impl Layout {
+    pub fn new(window: (f32, f32), margin: f32, flipped: bool) -> Self {
+        let width = window.0 - PANEL_WIDTH;
+        let tile = ((width.min(window.1) - 2.0 * margin) / 8.0).max(10.0);
+
+        Layout {
+            tile,
+            left: (width - 8.0 * tile) / 2.0,
+            top: (window.1 - 8.0 * tile) / 2.0,
+            margin,
+            flipped,
+        }
+    }
+
+    pub fn board_size(&self) -> f32 {
+        8.0 * self.tile
+    }
+
+    pub fn board_rect(&self) -> graphics::Rect {
+        graphics::Rect::new(self.left, self.top, self.board_size(), self.board_size())
+    }
+
+    pub fn center(&self) -> Vec2 {
+        Vec2::new(
+            self.left + self.board_size() / 2.0,
+            self.top + self.board_size() / 2.0,
+        )
+    }
+
+    pub fn right(&self) -> f32 {
+        self.left + self.board_size()
+    }
+
+    pub fn bottom(&self) -> f32 {
+        self.top + self.board_size()
+    }
+
+    // Middle of the column right of the board, for names and clocks
+    pub fn side_x(&self) -> f32 {
+        self.right() + self.margin / 2.0
+    }
+
+    // A flipped board is the same board turned half a circle, so the mapping is its own inverse
+    fn orient(&self, idx: usize) -> usize {
+        if self.flipped {
+            63 - idx
+        } else {
+            idx
+        }
+    }
+
+    // Top left corner of a square
+    pub fn square_pos(&self, idx: usize) -> Vec2 {
+        let idx = self.orient(idx);
+        Vec2::new(
+            (idx % 8) as f32 * self.tile + self.left,
+            (idx / 8) as f32 * self.tile + self.top,
+        )
+    }
+
+    // Square under a point, None outside the board
+    pub fn square_at(&self, x: f32, y: f32) -> Option<usize> {
+        let col = ((x - self.left) / self.tile).floor();
+        let row = ((y - self.top) / self.tile).floor();
+
+        if (0.0..8.0).contains(&col) && (0.0..8.0).contains(&row) {
+            Some(self.orient(row as usize * 8 + col as usize))
+        } else {
+            None
+        }
+    }
+
+    // Which side's name and clock go above the board
+    pub fn top_color(&self) -> Color {
+        if self.flipped {
+            Color::White
+        } else {
+            Color::Black
+        }
+    }
+
+    pub fn reset_button(&self) -> graphics::Rect {
+        graphics::Rect::new(self.left - self.margin / 2.0, self.top / 4.0, 150.0, 30.0)
+    }
+
+    pub fn draw_button(&self) -> graphics::Rect {
+        graphics::Rect::new(self.right() - 100.0, self.top / 4.0, 150.0, 30.0)
+    }
+
+    // Box for the draw offer in the middle of the board
+    pub fn banner(&self) -> graphics::Rect {
+        let center = self.center();
+        graphics::Rect::new(center.x - 200.0, center.y - 70.0, 400.0, 130.0)
+    }
+
+    pub fn accept_button(&self) -> graphics::Rect {
+        let center = self.center();
+        graphics::Rect::new(center.x - 180.0, center.y, 170.0, 40.0)
+    }
+
+    pub fn decline_button(&self) -> graphics::Rect {
+        let center = self.center();
+        graphics::Rect::new(center.x + 10.0, center.y, 170.0, 40.0)
+    }
+
+    // Lines of the game chooser and settings screen that fit on the board
+    pub fn menu_rows(&self) -> usize {
+        ((self.board_size() - 60.0) / 40.0).max(1.0) as usize
+    }
+
+    pub fn menu_row_y(&self, row: usize) -> f32 {
+        self.top + 50.0 + row as f32 * 40.0
+    }
+
+    pub fn menu_row_at(&self, y: f32) -> Option<usize> {
+        let row = ((y - self.top - 30.0) / 40.0).floor();
+        (row >= 0.0 && (row as usize) < self.menu_rows()).then_some(row as usize)
+    }
+
+    pub fn move_panel(&self) -> graphics::Rect {
+        let x = self.right() + self.margin;
+        graphics::Rect::new(x, self.top, PANEL_WIDTH - 20.0, self.board_size())
+    }
+
+    // Below the move list, only shown while looking at an earlier position
+    pub fn live_button(&self) -> graphics::Rect {
+        let panel = self.move_panel();
+        graphics::Rect::new(panel.x, panel.bottom() - 30.0, panel.w, 30.0)
+    }
+
+    // Lines of the move list that fit above the live button
+    pub fn move_rows(&self) -> usize {
+        ((self.move_panel().h - 50.0) / MOVE_ROW_HEIGHT).max(1.0) as usize
+    }
+
+    pub fn move_row_y(&self, row: usize) -> f32 {
+        self.top + 10.0 + row as f32 * MOVE_ROW_HEIGHT
+    }
+
+    // Line and column, 0 for white and 1 for black, of the move under a point
+    pub fn move_at(&self, x: f32, y: f32) -> Option<(usize, usize)> {
+        let panel = self.move_panel();
+        if !panel.contains(Vec2::new(x, y)) {
+            return None;
+        }
+
+        let row = ((y - self.top - 10.0) / MOVE_ROW_HEIGHT).floor();
+        let column = if x < self.move_column_x(1) { 0 } else { 1 };
+        (row >= 0.0 && (row as usize) < self.move_rows()).then_some((row as usize, column))
+    }
+
+    // Left edge of the white and black moves, the move number comes before them
+    pub fn move_column_x(&self, column: usize) -> f32 {
+        self.move_panel().x + 45.0 + column as f32 * 70.0
+    }
+
+    // Pixels per square, the size SVG pieces are rendered at
+    pub fn piece_size(&self) -> u32 {
+        self.tile.round() as u32
+    }
+
+    // Scale for a piece image to fill a square
+    pub fn piece_scale(&self, img: &graphics::Image) -> Vec2 {
+        Vec2::splat(self.tile / img.width() as f32)
+    }
+}
+
+// A move sliding into place, the board already holds the position after it
+pub struct Animation {
+    pub moved: Vec<(char, usize, usize)>,
+    pub captured: Option<(char, usize)>,
+    pub elapsed: Duration,
+    pub duration: Duration,
+}
+
+impl Animation {
+    pub fn new(board_str: &str, from: usize, to: usize, duration: Duration) -> Self {
+        Animation {
+            moved: moved_pieces(board_str, from, to),
+            captured: captured_piece(board_str, from, to),
+            elapsed: Duration::ZERO,
+            duration,
+        }
+    }
+
+    pub fn finished(&self) -> bool {
+        self.elapsed >= self.duration
+    }
+
+    // Eases out so pieces slow down as they land
+    pub fn progress(&self) -> f32 {
+        let t = (self.elapsed.as_secs_f32() / self.duration.as_secs_f32()).clamp(0.0, 1.0);
+        1.0 - (1.0 - t).powi(3)
+    }
+
+    // Squares whose pieces are drawn by the animation instead of the board
+    pub fn covers(&self, square: usize) -> bool {
+        self.moved.iter().any(|&(_, _, to)| to == square)
+    }
+}
+
+// Meshes that depend on the layout and colors, rebuilt when they change
+pub struct Meshes {
+    pub grid: graphics::Mesh,
+    pub selection: graphics::Mesh,
+    pub valid_circle: graphics::Mesh,
+    pub check_circle: graphics::Mesh,
+    pub last_move: graphics::Mesh,
+    pub annotation_circle: graphics::Mesh,
+    pub promotion: graphics::Mesh,
+    pub reset_button: graphics::Mesh,
+    pub draw_button: graphics::Mesh,
+    pub move_panel: graphics::Mesh,
+    pub live_button: graphics::Mesh,
+}
+
+impl Meshes {
+    pub fn new(ctx: &Context, layout: &Layout, theme: &Theme, accent: graphics::Color) -> Self {
+        let tile = layout.tile;
+        let square = graphics::Rect::new(0.0, 0.0, tile, tile);
+
+        let button = |rect| {
+            graphics::Mesh::new_rectangle(
+                ctx,
+                graphics::DrawMode::fill(),
+                rect,
+                graphics::Color::WHITE,
+            )
+            .unwrap()
+        };
+
+        Meshes {
+            grid: build_grid(ctx, layout, theme),
+            selection: graphics::Mesh::new_rectangle(
+                ctx,
+                graphics::DrawMode::stroke(tile / 20.0),
+                square,
+                accent,
+            )
+            .unwrap(),
+            valid_circle: graphics::Mesh::new_circle(
+                ctx,
+                graphics::DrawMode::fill(),
+                Vec2::new(0.0, 0.0),
+                tile / 10.0,
+                0.1,
+                graphics::Color::new(accent.r, accent.g, accent.b, 0.4),
+            )
+            .unwrap(),
+            check_circle: graphics::Mesh::new_circle(
+                ctx,
+                graphics::DrawMode::fill(),
+                Vec2::new(0.0, 0.0),
+                tile / 4.0,
+                0.1,
+                theme.check_color(),
+            )
+            .unwrap(),
+            last_move: graphics::Mesh::new_rectangle(
+                ctx,
+                graphics::DrawMode::fill(),
+                square,
+                theme.last_move_color(),
+            )
+            .unwrap(),
+            annotation_circle: graphics::Mesh::new_circle(
+                ctx,
+                graphics::DrawMode::stroke(tile / 14.0),
+                Vec2::new(0.0, 0.0),
+                tile * 0.45,
+                0.1,
+                theme.annotation_color(),
+            )
+            .unwrap(),
+            promotion: graphics::Mesh::new_rectangle(
+                ctx,
+                graphics::DrawMode::fill(),
+                square,
+                theme.promotion_color(),
+            )
+            .unwrap(),
+            reset_button: button(layout.reset_button()),
+            draw_button: button(layout.draw_button()),
+            move_panel: graphics::Mesh::new_rectangle(
+                ctx,
+                graphics::DrawMode::fill(),
+                layout.move_panel(),
+                graphics::Color::from_rgba(255, 255, 255, 20),
+            )
+            .unwrap(),
+            live_button: button(layout.live_button()),
+        }
+    }
+}
+
+// Arrows between square centers, built every frame since they come and go with clicks
+pub fn build_arrows(
+    ctx: &Context,
+    layout: &Layout,
+    arrows: &[(usize, usize)],
+    color: graphics::Color,
+) -> GameResult<graphics::Mesh> {
+    let mb = &mut graphics::MeshBuilder::new();
+    let half = Vec2::splat(layout.tile / 2.0);
+    let head_length = layout.tile * 0.4;
+    let head_width = layout.tile * 0.25;
+
+    for &(from, to) in arrows {
+        let start = layout.square_pos(from) + half;
+        let tip = layout.square_pos(to) + half;
+        let dir = (tip - start).normalize();
+        let side = dir.perp() * head_width;
+        let base = tip - dir * head_length;
+
+        mb.line(
+            &[start + dir * layout.tile * 0.2, base],
+            layout.tile * 0.15,
+            color,
+        )?;
+        mb.polygon(
+            graphics::DrawMode::fill(),
+            &[tip, base + side, base - side],
+            color,
+        )?;
+    }
+
+    Ok(graphics::Mesh::from_data(ctx, mb.build()))
+}
+
+pub fn build_grid(ctx: &Context, layout: &Layout, theme: &Theme) -> graphics::Mesh {
+    let mb = &mut graphics::MeshBuilder::new();
+    for row in 0..8 {
+        for col in 0..8 {
+            let tile_color = if (row + col) % 2 == 0 {
+                theme.light_color()
+            } else {
+                theme.dark_color()
+            };
+
+            let rect = graphics::Rect::new(
+                col as f32 * layout.tile,
+                row as f32 * layout.tile,
+                layout.tile,
+                layout.tile,
+            );
+            mb.rectangle(graphics::DrawMode::fill(), rect, tile_color)
+                .expect("Failed to build grid tile");
+        }
+    }
+
+    graphics::Mesh::from_data(ctx, mb.build())
+}
@@ -0,0 +1,279 @@
+use crate::*;
+use std::io::{BufRead, BufReader, Write};
+use std::process::{Child, ChildStdin, Command, Stdio};
+use std::sync::mpsc::{self, Receiver};
+
+pub struct EngineOptions {
+    pub movetime: Option<u64>,
+    pub depth: Option<u64>,
+    pub options: Vec<(String, String)>,
+}
+
+// A UCI engine running as a subprocess
+pub struct Engine {
+    pub name: String,
+    child: Child,
+    stdin: ChildStdin,
+    lines: Receiver<String>,
+    go: String,
+    position: Option<String>,
+    thinking: bool,
+    stale: bool,
+}
+
+impl Engine {
+    pub fn new(path: &str, opts: &EngineOptions) -> Result<Self, String> {
+        let mut child = Command::new(path)
+            .stdin(Stdio::piped())
+            .stdout(Stdio::piped())
+            .spawn()
+            .map_err(|e| format!("could not start {}: {}", path, e))?;
+
+        let stdin = child.stdin.take().unwrap();
+        let stdout = child.stdout.take().unwrap();
+
+        // READ ENGINE OUTPUT ON ITS OWN THREAD SO THE GAME NEVER BLOCKS ON IT
+        let (tx, lines) = mpsc::channel();
+        std::thread::spawn(move || {
+            for line in BufReader::new(stdout).lines() {
+                let Ok(line) = line else {
+                    break;
+                };
+                if tx.send(line).is_err() {
+                    break;
+                }
+            }
+        });
+
+        let go = match (opts.movetime, opts.depth) {
+            (_, Some(depth)) => format!("go depth {}", depth),
+            (Some(movetime), None) => format!("go movetime {}", movetime),
+            (None, None) => "go movetime 1000".to_string(),
+        };
+
+        let mut engine = Self {
+            name: path.to_string(),
+            child,
+            stdin,
+            lines,
+            go,
+            position: None,
+            thinking: false,
+            stale: false,
+        };
+
+        engine.write("uci")?;
+        for line in engine.wait_for("uciok")? {
+            if let Some(name) = line.strip_prefix("id name ") {
+                engine.name = name.to_string();
+            }
+        }
+
+        for (name, value) in opts.options.iter() {
+            engine.write(&format!("setoption name {} value {}", name, value))?;
+        }
+
+        engine.write("ucinewgame")?;
+        engine.write("isready")?;
+        engine.wait_for("readyok")?;
+
+        Ok(engine)
+    }
+
+    fn write(&mut self, cmd: &str) -> Result<(), String> {
+        writeln!(self.stdin, "{}", cmd).map_err(|e| format!("could not write to engine: {}", e))
+    }
+
+    // Block until the engine prints `token`, returns the lines before it
+    fn wait_for(&mut self, token: &str) -> Result<Vec<String>, String> {
+        let mut lines = Vec::new();
+
+        loop {
+            match self.lines.recv_timeout(Duration::from_secs(10)) {
+                Ok(line) if line.trim() == token => return Ok(lines),
+                Ok(line) => lines.push(line),
+                Err(_) => return Err(format!("engine did not answer with {}", token)),
+            }
+        }
+    }
+
+    // Long algebraic notation like e2e4 or e7e8q, anything else such as 0000 or (none) is no move
+    fn parse_bestmove(mv: &str) -> Option<net::Move> {
+        let valid = match mv.as_bytes() {
+            [f1, r1, f2, r2, rest @ ..] => {
+                [f1, f2].iter().all(|f| (b'a'..=b'h').contains(f))
+                    && [r1, r2].iter().all(|r| (b'1'..=b'8').contains(r))
+                    && matches!(rest, [] | [b'q' | b'r' | b'b' | b'n'])
+            }
+            _ => false,
+        };
+
+        if !valid {
+            return None;
+        }
+
+        let from = str_to_idx(&mv[0..2]);
+        let to = str_to_idx(&mv[2..4]);
+        let promotion = mv.chars().nth(4).and_then(char_to_promotion);
+
+        Some(net::Move {
+            from: pos_int_to_tuple(from),
+            to: pos_int_to_tuple(to),
+            offer_draw: false,
+            promotion,
+            forfeit: false,
+        })
+    }
+}
+
+impl Opponent for Engine {
+    fn poll_move(&mut self) -> Option<net::Move> {
+        if !self.thinking {
+            let position = self.position.clone()?;
+            self.write(&position).ok()?;
+            let go = self.go.clone();
+            self.write(&go).ok()?;
+            self.thinking = true;
+        }
+
+        while let Ok(line) = self.lines.try_recv() {
+            let Some(rest) = line.strip_prefix("bestmove ") else {
+                continue;
+            };
+
+            self.thinking = false;
+
+            // ANSWER TO A POSITION THAT HAS SINCE CHANGED
+            if self.stale {
+                self.stale = false;
+                continue;
+            }
+
+            log!(LogLevel::Debug, "Engine plays {}", rest);
+            return Engine::parse_bestmove(rest.split_whitespace().next().unwrap_or(""));
+        }
+
+        None
+    }
+
+    fn ack(&mut self, ack: net::Ack) {
+        if !ack.ok {
+            log!(LogLevel::Warn, "Engine played an illegal move");
+        }
+    }
+
+    fn position_changed(&mut self, position_info: &PositionInfo) {
+        let mut position = match position_info.start_fen {
+            Some(fen) => format!("position fen {}", fen),
+            None => "position startpos".to_string(),
+        };
+
+        if !position_info.moves.is_empty() {
+            position.push_str(" moves");
+            for m in position_info.moves {
+                position.push(' ');
+                position.push_str(&move_to_uci(m));
+            }
+        }
+
+        if self.thinking {
+            let _ = self.write("stop");
+            self.stale = true;
+        }
+
+        self.position = Some(position);
+    }
+}
+
+impl Drop for Engine {
+    fn drop(&mut self) {
+        let _ = self.write("quit");
+        let _ = self.child.kill();
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn square(name: &str) -> (u8, u8) {
+        pos_int_to_tuple(str_to_idx(name))
+    }
+
+    #[test]
+    fn parses_moves_and_promotions() {
+        let mv = Engine::parse_bestmove("e2e4").unwrap();
+        assert_eq!((mv.from, mv.to), (square("e2"), square("e4")));
+        assert!(mv.promotion.is_none());
+
+        let mv = Engine::parse_bestmove("e7e8q").unwrap();
+        assert!(matches!(mv.promotion, Some(net::PromotionPiece::Queen)));
+    }
+
+    #[test]
+    fn rejects_null_and_malformed_moves() {
+        for mv in [
+            "0000", "(none)", "", "e2", "e2e9", "i2e4", "e2e4k", "e2e4qq", "E2E4",
+        ] {
+            assert!(Engine::parse_bestmove(mv).is_none(), "{}", mv);
+        }
+    }
+
+    // The fake engine is a shell script, so this only runs where there is a /bin/sh
+    #[cfg(unix)]
+    mod scripted {
+        use super::*;
+        use std::os::unix::fs::PermissionsExt;
+
+        // Answers the handshake and always plays e2e4
+        const FAKE_ENGINE: &str = "#!/bin/sh
+while read -r cmd; do
+    case \"$cmd\" in
+        uci) echo 'id name Scripted'; echo 'uciok' ;;
+        isready) echo 'readyok' ;;
+        go*) echo 'info depth 1 score cp 20'; echo 'bestmove e2e4 ponder e7e5' ;;
+        quit) exit 0 ;;
+    esac
+done
+";
+
+        #[test]
+        fn plays_against_a_scripted_engine() {
+            let path = std::env::temp_dir().join(format!("fake-engine-{}.sh", std::process::id()));
+            std::fs::write(&path, FAKE_ENGINE).unwrap();
+            std::fs::set_permissions(&path, std::fs::Permissions::from_mode(0o755)).unwrap();
+
+            let opts = EngineOptions {
+                movetime: Some(10),
+                depth: None,
+                options: vec![("Hash".to_string(), "16".to_string())],
+            };
+            let mut engine = Engine::new(path.to_str().unwrap(), &opts).unwrap();
+            assert_eq!(engine.name, "Scripted");
+
+            let valid_moves = std::array::from_fn(|_| Vec::new());
+            engine.position_changed(&PositionInfo {
+                start_fen: None,
+                moves: &[],
+                board_str: "",
+                turn: Color::White,
+                valid_moves: &valid_moves,
+            });
+
+            let mut mv = None;
+            for _ in 0..500 {
+                mv = engine.poll_move();
+                if mv.is_some() {
+                    break;
+                }
+                std::thread::sleep(Duration::from_millis(10));
+            }
+
+            let mv = mv.expect("engine never answered");
+            assert_eq!((mv.from, mv.to), (square("e2"), square("e4")));
+
+            drop(engine);
+            let _ = std::fs::remove_file(path);
+        }
+    }
+}
@@ -1,10 +1,12 @@
 use arvidkr_chess::*;
 use chess_networking as net;
-use ggez::event::{self, EventHandler, MouseButton};
+use ggez::event::{self, EventHandler, EventLoop, MouseButton};
 use ggez::graphics::{self};
+use ggez::input::keyboard::{KeyCode, KeyInput, KeyMods};
 use ggez::{glam::*, Context, ContextBuilder, GameResult};
 use std::env;
 use std::path;
+use std::process::ExitCode;
 use std::str::FromStr;
 use std::time::Duration;
 
@@ -17,8 +19,38 @@ use draw::*;
 mod network;
 use network::*;
 
-const TILE_SIZE: f32 = 100.0;
-const OFFSET: f32 = 100.0;
+mod clock;
+use clock::*;
+
+mod pgn;
+use pgn::*;
+
+mod opponent;
+use opponent::*;
+
+mod engine;
+use engine::*;
+
+mod ai;
+use ai::*;
+
+mod config;
+use config::*;
+
+mod logging;
+use logging::*;
+
+mod cli;
+use cli::*;
+
+mod theme;
+use theme::*;
+
+mod settings;
+use settings::*;
+
+// ILLEGAL MOVES IN A ROW BEFORE THE ILLEGAL MOVE POLICY KICKS IN
+const ILLEGAL_MOVE_LIMIT: u32 = 3;
 
 #[derive(Debug, Clone, Copy, PartialEq)]
 enum ConnectionType {
@@ -26,38 +58,159 @@ enum ConnectionType {
     Client,
 }
 
-fn main() {
-    let args: Vec<String> = env::args().collect();
+fn build_context(window: (f32, f32)) -> (Context, EventLoop<()>) {
+    let resource_dir = path::PathBuf::from("./resources");
+
+    let mode = ggez::conf::WindowMode::default()
+        .dimensions(window.0, window.1)
+        .resizable(true)
+        .min_dimensions(MIN_WINDOW.0, MIN_WINDOW.1);
+
+    ContextBuilder::new("chess", "Laim")
+        .add_resource_path(resource_dir)
+        .window_mode(mode)
+        .window_setup(ggez::conf::WindowSetup::default().title("ULTIMEATE CHESS GAME!!?1"))
+        .build()
+        .expect("gg, could not create ggez context :(")
+}
+
+fn main() -> ExitCode {
+    let args: Vec<String> = env::args().skip(1).collect();
+
+    let cli = match parse_args(&args) {
+        Ok(cli) => cli,
+        Err(CliError::Help) => {
+            println!("{}", HELP);
+            return ExitCode::SUCCESS;
+        }
+        Err(e) => {
+            eprintln!("{}", e);
+            return ExitCode::FAILURE;
+        }
+    };
+
+    set_log_level(cli.log_level);
+
+    // SETTINGS FROM THE CONFIG FILE, THE COMMAND LINE WINS FOR THIS RUN ONLY
+    let file_config = load_config();
+    let mut config = file_config.clone();
+
+    if let Some(name) = &cli.name {
+        config.name = Some(name.clone());
+    }
+
+    if let Some(color) = cli.color {
+        config.color = Some(color);
+    }
+
+    if let Some(port) = cli.port {
+        config.port = port;
+    }
+
+    if let Some(window) = cli.window {
+        config.window = window;
+    }
+
+    if let Some(theme) = &cli.theme {
+        config.theme = theme.clone();
+    }
 
-    if args.len() != 3 {
-        println!("Usage: cargo run <addr> <role: \"client\" | \"server\">");
-        std::process::exit(1);
+    if let Some(pieces) = &cli.pieces {
+        config.pieces = pieces.clone();
     }
 
-    let addr = &args[1];
-    let role = &args[2];
+    // CHECK EVERYTHING THAT CAN FAIL BEFORE OPENING THE WINDOW
+    let mut games = Vec::new();
+    let mut engine = None;
+
+    match &cli.mode {
+        Mode::Replay(path) => {
+            let text = match std::fs::read_to_string(path) {
+                Ok(text) => text,
+                Err(e) => {
+                    eprintln!("Could not read {}: {}", path, e);
+                    return ExitCode::FAILURE;
+                }
+            };
+
+            games = parse_pgn(&text);
+
+            if games.is_empty() {
+                eprintln!("No games found in {}", path);
+                return ExitCode::FAILURE;
+            }
+        }
+        Mode::Engine(path) => {
+            let opts = EngineOptions {
+                movetime: cli.movetime,
+                depth: cli.depth,
+                options: cli.options.clone(),
+            };
 
-    let role = match role as &str {
-        "client" => ConnectionType::Client,
-        "server" => ConnectionType::Server,
-        _ => {
-            println!("Invalid role, must be client or server");
-            std::process::exit(1);
+            match Engine::new(path, &opts) {
+                Ok(started) => engine = Some(started),
+                Err(e) => {
+                    eprintln!("Could not start engine: {}", e);
+                    return ExitCode::FAILURE;
+                }
+            }
         }
+        _ => (),
+    }
+
+    let my_color = if wants_white(config.color) {
+        Color::White
+    } else {
+        Color::Black
     };
 
-    let resource_dir = path::PathBuf::from("./resources");
+    let (mut ctx, event_loop) = build_context(config.window);
+
+    let mut chess = match &cli.mode {
+        Mode::Host | Mode::Join(_) => {
+            let role = match cli.mode {
+                Mode::Host => ConnectionType::Server,
+                _ => ConnectionType::Client,
+            };
 
-    let mode = ggez::conf::WindowMode::default().dimensions(1000.0, 1000.0);
+            let chess = Chess::new(
+                &mut ctx,
+                &cli.address(config.port),
+                role,
+                cli.time,
+                cli.inc,
+                cli.fen.clone(),
+                &config,
+            );
 
-    let (mut ctx, event_loop) = ContextBuilder::new("chess", "Laim")
-        .add_resource_path(resource_dir)
-        .window_mode(mode)
-        .window_setup(ggez::conf::WindowSetup::default().title("ULTIMEATE CHESS GAME!!?1"))
-        .build()
-        .expect("gg, could not create ggez context :(");
+            match chess {
+                Ok(chess) => chess,
+                Err(e) => {
+                    eprintln!("Could not start the game: {}", e);
+                    return ExitCode::FAILURE;
+                }
+            }
+        }
+        Mode::Local => Chess::new_local(&mut ctx, cli.time, cli.inc, cli.fen.clone()),
+        Mode::Analyze => Chess::new_analysis(&mut ctx, cli.fen.clone()),
+        Mode::Ai => {
+            let ai = Ai::new(AiOptions {
+                depth: cli.depth.unwrap_or(4) as u32,
+                time: Duration::from_millis(cli.think),
+            });
+
+            Chess::new_ai(&mut ctx, ai, my_color, cli.fen.clone())
+        }
+        Mode::Engine(_) => {
+            let engine = engine.take().expect("Engine was started above");
+            Chess::new_engine(&mut ctx, engine, my_color, cli.fen.clone())
+        }
+        Mode::Replay(_) => Chess::new_replay(&mut ctx, games),
+    };
 
-    let chess = Chess::new(&mut ctx, addr, role);
+    chess.illegal_policy = cli.illegal;
+    chess.file_config = file_config;
+    chess.apply_config(&mut ctx, config);
 
     event::run(ctx, event_loop, chess);
 }
@@ -72,14 +225,80 @@ struct Chess {
     valid_moves: [Vec<usize>; 64],
     turn: Color,
     my_color: Color,
-    grid: graphics::Mesh,
-    reset_button_rect: graphics::Rect,
-    reset_button_mesh: graphics::Mesh,
-    piece_mesh: graphics::Mesh,
-    valid_circle_mesh: graphics::Mesh,
-    check_circle_mesh: graphics::Mesh,
+    config: Config,
+    // WHAT THE SETTINGS FILE HOLDS, WITHOUT THE COMMAND LINE OVERRIDES
+    file_config: Config,
+    layout: Layout,
+    meshes: Meshes,
+    settings: Option<SettingsScreen>,
+    pending_promotion: Option<(usize, usize)>,
+    animation: Option<Animation>,
+    // RIGHT BUTTON ANNOTATIONS, CLEARED BY THE NEXT MOVE
+    arrows: Vec<(usize, usize)>,
+    circles: Vec<usize>,
+    annotation_start: Option<usize>,
+    pending_move: Option<(usize, usize, Option<net::PromotionPiece>)>,
+    confirm_forfeit: bool,
+    // OFFER A DRAW WITH OUR NEXT MOVE
+    offer_draw: bool,
+    // OUR OFFER IS WAITING FOR THE ACK OF THE MOVE IT WAS SENT WITH
+    draw_offered: bool,
+    // THE OPPONENT'S OFFER, ITS MOVE IS NOT ACKED UNTIL WE ANSWER
+    incoming_offer: bool,
+    // SHOWN WHEN THE OPPONENT SEES THE GAME ENDING DIFFERENTLY
+    warning: Option<String>,
+    illegal_policy: IllegalMovePolicy,
+    // ILLEGAL MOVES FROM THE OPPONENT IN A ROW
+    illegal_moves: u32,
     status: Status,
-    conn: Connection,
+    start_fen: Option<String>,
+    history: Vec<PlayedMove>,
+    white_name: String,
+    black_name: String,
+    pgn_path: String,
+    // SECONDS PER SIDE AND INCREMENT, EVERY NEW GAME STARTS A FRESH CLOCK FROM THEM
+    time_control: Option<(u64, u64)>,
+    clock: Option<Clock>,
+    replay: Option<Replay>,
+    view: Option<View>,
+    // FIRST LINE OF THE MOVE LIST, NONE FOLLOWS THE LATEST MOVE
+    move_scroll: Option<usize>,
+    local: bool,
+    // FREE BOARD WITHOUT RESULTS OR SAVING
+    analysis: bool,
+    opponent: Option<Box<dyn Opponent>>,
+}
+
+// AN EARLIER POSITION PICKED FROM THE MOVE LIST, THE GAME GOES ON UNDERNEATH
+struct View {
+    ply: usize,
+    board: Board,
+    board_str: String,
+}
+
+struct Replay {
+    games: Vec<PgnGame>,
+    game: Option<usize>,
+    cursor: usize,
+}
+
+impl Replay {
+    // FIRST ROW SHOWN IN THE GAME CHOOSER, KEEPS THE CURSOR IN VIEW
+    fn first_row(&self, rows: usize) -> usize {
+        self.cursor
+            .saturating_sub(rows / 2)
+            .min(self.games.len().saturating_sub(rows))
+    }
+}
+
+// ADD THE ITEM IF IT IS MISSING, OTHERWISE REMOVE IT
+fn toggle<T: PartialEq>(list: &mut Vec<T>, item: T) {
+    match list.iter().position(|x| *x == item) {
+        Some(i) => {
+            list.remove(i);
+        }
+        None => list.push(item),
+    }
 }
 
 fn pos_int_to_tuple(idx: usize) -> (u8, u8) {
@@ -95,254 +314,981 @@ fn pos_tuple_to_int(pos: (u8, u8)) -> usize {
 }
 
 impl Chess {
-    pub fn new(ctx: &mut Context, addr: &str, role: ConnectionType) -> Chess {
-        let mb = &mut graphics::MeshBuilder::new();
-        for row in 0..8 {
-            for col in 0..8 {
-                let tile_color = if (row + col) % 2 == 0 {
-                    graphics::Color::from_rgb(255, 255, 255)
-                } else {
-                    graphics::Color::from_rgb(0, 0, 0)
-                };
-
-                let rect = graphics::Rect::new(
-                    col as f32 * TILE_SIZE,
-                    row as f32 * TILE_SIZE,
-                    TILE_SIZE,
-                    TILE_SIZE,
-                );
-                mb.rectangle(graphics::DrawMode::fill(), rect, tile_color)
-                    .expect("Failed to build grid tile");
-            }
-        }
-
-        let grid = graphics::Mesh::from_data(ctx, mb.build());
-
+    pub fn new(
+        ctx: &mut Context,
+        addr: &str,
+        role: ConnectionType,
+        time: Option<u64>,
+        inc: Option<u64>,
+        fen: Option<String>,
+        config: &Config,
+    ) -> Result<Chess, String> {
         let mut conn = match role {
             ConnectionType::Server => Connection::new_server(addr),
             ConnectionType::Client => Connection::new_client(addr),
         };
         std::thread::sleep(Duration::from_secs(1));
 
-        let mut board = Board::new();
-        board.init_board();
-
         let mut my_color = Color::White;
+        let time_control;
+        let start_fen;
+        let my_name;
+        let opp_name;
 
         if role == ConnectionType::Client {
+            my_name = config
+                .name
+                .clone()
+                .unwrap_or("The weather outside is rizzy".to_string());
+
+            let want_white = wants_white(config.color);
+
             let start = net::Start {
-                is_white: true,
-                name: Some("The weather outside is rizzy".to_string()),
-                fen: None,
-                time: None,
-                inc: None,
+                is_white: want_white,
+                name: Some(my_name.clone()),
+                fen: fen.clone(),
+                time,
+                inc,
             };
-            conn.send(start);
+            conn.send_start(start);
 
-            let ret_start = conn.receive_skibidi::<net::Start>();
+            let ret_start = conn.wait_start()?;
 
             if ret_start.is_white {
                 my_color = Color::Black;
             }
 
-            println!("{:?}", ret_start);
+            if ret_start.is_white == want_white {
+                log!(LogLevel::Info, "The server took the color we asked for");
+            }
+
+            // THE SERVER DECIDES THE TIME CONTROL AND POSITION
+            time_control = (ret_start.time, ret_start.inc);
+            start_fen = ret_start.fen.clone();
+            opp_name = ret_start.name.clone();
+
+            if let Some(fen) = &start_fen {
+                validate_fen(fen).map_err(|e| format!("the server sent an invalid FEN: {}", e))?;
+            }
+
+            log!(LogLevel::Debug, "{:?}", ret_start);
         } else {
-            let start = conn.receive_skibidi::<net::Start>();
+            my_name = config
+                .name
+                .clone()
+                .unwrap_or("But the fire is so skibidi".to_string());
+
+            let start = conn.wait_start()?;
+
+            log!(LogLevel::Debug, "{:?}", start);
+
+            opp_name = start.name.clone();
+
+            // USE THE CLIENTS POSITION IF IT SENT ONE, ELSE OUR OWN
+            start_fen = match start.fen {
+                Some(fen) => {
+                    if let Err(e) = validate_fen(&fen) {
+                        conn.reject_start();
+                        return Err(format!("the opponent sent an invalid FEN: {}", e));
+                    }
+                    Some(fen)
+                }
+                None => fen,
+            };
+
+            // USE THE CLIENTS TIME CONTROL IF IT SENT ONE, ELSE OUR OWN
+            time_control = if start.time.is_some() {
+                (start.time, start.inc)
+            } else {
+                (time, inc)
+            };
 
-            println!("{:?}", start);
+            // GIVE THE CLIENT ITS COLOR UNLESS WE WANT THE SAME ONE
+            let server_white = match config.color {
+                Some(ColorPreference::White) => true,
+                Some(ColorPreference::Black) => false,
+                Some(ColorPreference::Random) | None => !start.is_white,
+            };
+
+            if server_white == start.is_white {
+                log!(
+                    LogLevel::Info,
+                    "Both players want the same color, keeping ours"
+                );
+            }
 
             let ret_start = net::Start {
-                is_white: !start.is_white,
-                name: Some("But the fire is so skibidi".to_string()),
-                fen: None,
-                time: None,
-                inc: None,
+                is_white: server_white,
+                name: Some(my_name.clone()),
+                fen: start_fen.clone(),
+                time: time_control.0,
+                inc: time_control.1,
             };
-            conn.send(ret_start);
+            conn.send_start(ret_start);
 
-            if start.is_white {
+            if !server_white {
                 my_color = Color::Black;
             }
         }
 
-        let board_str = invert_boardstr(board.get_boardinfo()[7..71].to_string());
+        let opp_name = opp_name.unwrap_or("?".to_string());
+        let (white_name, black_name) = if my_color == Color::White {
+            (my_name, opp_name)
+        } else {
+            (opp_name, my_name)
+        };
 
-        let piece_mesh = graphics::Mesh::new_rectangle(
+        let time_control = time_control
+            .0
+            .map(|time| (time, time_control.1.unwrap_or(0)));
+
+        Ok(Chess::with_game(
             ctx,
-            graphics::DrawMode::stroke(5.0),
-            graphics::Rect::new(0.0, 0.0, TILE_SIZE, TILE_SIZE),
-            graphics::Color::from_rgba(199, 38, 239, 255),
-        )
-        .unwrap();
+            Some(Box::new(conn)),
+            my_color,
+            start_fen,
+            white_name,
+            black_name,
+            time_control,
+        ))
+    }
 
-        let valid_circle_mesh = graphics::Mesh::new_circle(
+    pub fn new_local(
+        ctx: &mut Context,
+        time: Option<u64>,
+        inc: Option<u64>,
+        fen: Option<String>,
+    ) -> Chess {
+        let time_control = time.map(|time| (time, inc.unwrap_or(0)));
+
+        let mut chess = Chess::with_game(
             ctx,
-            graphics::DrawMode::fill(),
-            Vec2::new(0.0, 0.0),
-            10.0,
-            0.1,
-            graphics::Color::from_rgba(199, 38, 239, 100),
-        )
-        .unwrap();
+            None,
+            Color::White,
+            fen,
+            "White".to_string(),
+            "Black".to_string(),
+            time_control,
+        );
+
+        chess.local = true;
+        chess.update_board();
+        chess
+    }
+
+    pub fn new_analysis(ctx: &mut Context, fen: Option<String>) -> Chess {
+        let mut chess = Chess::new_local(ctx, None, None, fen);
+        chess.analysis = true;
+        chess
+    }
+
+    // USE NEW SETTINGS, THE LAYOUT AND MESHES ARE REBUILT FROM THEM
+    fn apply_config(&mut self, ctx: &mut Context, mut config: Config) {
+        config.window = clamp_window(config.window);
+
+        if config.window != self.config.window {
+            if let Err(e) = ctx.gfx.set_drawable_size(config.window.0, config.window.1) {
+                log!(LogLevel::Error, "Could not resize window: {}", e);
+            }
+        }
+
+        let pieces_changed = config.pieces != self.config.pieces;
+        let layout = Layout::new(config.window, config.margin, self.layout.flipped);
+        self.config = config;
+        self.set_layout(ctx, layout, pieces_changed);
+    }
+
+    // SVG PIECES ARE RENDERED AGAIN WHEN THE SQUARES CHANGE SIZE
+    fn set_layout(&mut self, ctx: &Context, layout: Layout, reload_pieces: bool) {
+        let resized = layout.piece_size() != self.layout.piece_size();
+        self.layout = layout;
+
+        if reload_pieces || (resized && is_svg_set(ctx, &self.config.pieces)) {
+            self.piece_images = load_piece_images(ctx, &self.config.pieces, layout.piece_size());
+        }
+
+        self.rebuild_meshes(ctx);
+    }
 
-        let check_circle_mesh = graphics::Mesh::new_circle(
+    // AN UNKNOWN THEME IN THE CONFIG FALLS BACK TO THE FIRST ONE
+    fn theme(&self) -> &'static Theme {
+        find_theme(&self.config.theme).unwrap_or(&THEMES[0])
+    }
+
+    fn rebuild_meshes(&mut self, ctx: &Context) {
+        self.meshes = Meshes::new(ctx, &self.layout, self.theme(), self.config.accent_color());
+    }
+
+    pub fn new_engine(
+        ctx: &mut Context,
+        engine: Engine,
+        my_color: Color,
+        fen: Option<String>,
+    ) -> Chess {
+        let engine_name = engine.name.clone();
+        let (white_name, black_name) = if my_color == Color::White {
+            ("You".to_string(), engine_name)
+        } else {
+            (engine_name, "You".to_string())
+        };
+
+        Chess::with_game(
             ctx,
-            graphics::DrawMode::fill(),
-            Vec2::new(0.0, 0.0),
-            25.0,
-            0.1,
-            graphics::Color::from_rgba(255, 0, 0, 100),
+            Some(Box::new(engine)),
+            my_color,
+            fen,
+            white_name,
+            black_name,
+            None,
         )
-        .unwrap();
+    }
 
-        let reset_button_rect = graphics::Rect::new(50.0, 25.0, 150.0, 30.0);
+    pub fn new_ai(ctx: &mut Context, ai: Ai, my_color: Color, fen: Option<String>) -> Chess {
+        let (white_name, black_name) = if my_color == Color::White {
+            ("You".to_string(), "Computer".to_string())
+        } else {
+            ("Computer".to_string(), "You".to_string())
+        };
 
-        let reset_button_mesh = graphics::Mesh::new_rectangle(
+        Chess::with_game(
             ctx,
-            graphics::DrawMode::fill(),
-            reset_button_rect,
-            graphics::Color::from_rgba(255, 255, 255, 255),
+            Some(Box::new(ai)),
+            my_color,
+            fen,
+            white_name,
+            black_name,
+            None,
         )
-        .unwrap();
+    }
+
+    pub fn new_replay(ctx: &mut Context, games: Vec<PgnGame>) -> Chess {
+        let single_game = games.len() == 1;
+
+        let mut chess = Chess::with_game(
+            ctx,
+            None,
+            Color::None,
+            None,
+            "?".to_string(),
+            "?".to_string(),
+            None,
+        );
+
+        chess.replay = Some(Replay {
+            games,
+            game: None,
+            cursor: 0,
+        });
+
+        // ONLY SHOW THE GAME CHOOSER IF THERE IS SOMETHING TO CHOOSE
+        if single_game {
+            chess.load_replay_game(0);
+        }
 
-        let valid_moves = generate_valid_moves(&mut board);
-        Chess {
+        chess
+    }
+
+    fn with_game(
+        ctx: &mut Context,
+        opponent: Option<Box<dyn Opponent>>,
+        my_color: Color,
+        start_fen: Option<String>,
+        white_name: String,
+        black_name: String,
+        time_control: Option<(u64, u64)>,
+    ) -> Chess {
+        let config = Config::default();
+        // BLACK PLAYS FROM THE BOTTOM
+        let layout = Layout::new(config.window, config.margin, my_color == Color::Black);
+        let meshes = Meshes::new(ctx, &layout, &THEMES[0], config.accent_color());
+
+        let mut chess = Chess {
             status: Status::Active,
-            piece_images: load_piece_images(ctx),
+            piece_images: load_piece_images(ctx, &config.pieces, layout.piece_size()),
             turn: Color::White,
             my_color,
-            board,
-            board_str,
+            board: Board::new(),
+            board_str: String::new(),
             selected_piece: None,
             dragging: false,
             mouse_pos: (0.0, 0.0),
-            valid_moves,
-            grid,
-            reset_button_mesh,
-            reset_button_rect,
-            piece_mesh,
-            valid_circle_mesh,
-            check_circle_mesh,
-            conn,
+            valid_moves: std::array::from_fn(|_| Vec::new()),
+            file_config: config.clone(),
+            config,
+            layout,
+            meshes,
+            settings: None,
+            pending_promotion: None,
+            animation: None,
+            arrows: Vec::new(),
+            circles: Vec::new(),
+            annotation_start: None,
+            pending_move: None,
+            confirm_forfeit: false,
+            offer_draw: false,
+            draw_offered: false,
+            incoming_offer: false,
+            warning: None,
+            illegal_policy: IllegalMovePolicy::Warn,
+            illegal_moves: 0,
+            start_fen,
+            history: Vec::new(),
+            white_name,
+            black_name,
+            pgn_path: default_pgn_path(),
+            time_control,
+            clock: None,
+            replay: None,
+            view: None,
+            move_scroll: None,
+            local: false,
+            analysis: false,
+            opponent,
+        };
+
+        chess.reset_board();
+        chess
+    }
+
+    fn reset_board(&mut self) {
+        self.board = Board::new();
+        self.board.init_board();
+
+        if let Some(fen) = &self.start_fen {
+            load_fen(&mut self.board, fen).expect("Start FEN was validated before the game");
+        }
+
+        self.turn = turn_from_boardinfo(&self.board.get_boardinfo());
+        self.selected_piece = None;
+        self.pending_promotion = None;
+        self.pending_move = None;
+        self.confirm_forfeit = false;
+        self.offer_draw = false;
+        self.draw_offered = false;
+        self.incoming_offer = false;
+        self.warning = None;
+        self.illegal_moves = 0;
+        self.arrows.clear();
+        self.circles.clear();
+        self.animation = None;
+        self.view = None;
+        self.clock = self.time_control.map(|(time, inc)| Clock::new(time, inc));
+        self.status = Status::Active;
+        self.history.clear();
+        self.update_board();
+        self.notify_opponent();
+    }
+
+    fn notify_opponent(&mut self) {
+        if let Some(opponent) = &mut self.opponent {
+            opponent.position_changed(&PositionInfo {
+                start_fen: self.start_fen.as_deref(),
+                moves: &self.history,
+                board_str: &self.board_str,
+                turn: self.turn,
+                valid_moves: &self.valid_moves,
+            });
+        }
+    }
+
+    fn load_replay_game(&mut self, game: usize) {
+        let Some(replay) = &mut self.replay else {
+            return;
+        };
+
+        let pgn = &replay.games[game];
+        let fen = pgn.tag("FEN").map(|fen| fen.to_string());
+
+        if let Some(fen) = &fen {
+            if let Err(e) = validate_fen(fen) {
+                log!(LogLevel::Error, "Could not load game, invalid FEN: {}", e);
+                return;
+            }
+        }
+
+        self.white_name = pgn.tag("White").unwrap_or("?").to_string();
+        self.black_name = pgn.tag("Black").unwrap_or("?").to_string();
+        replay.game = Some(game);
+        self.start_fen = fen;
+        self.reset_board();
+    }
+
+    // PLAY THE NEXT MOVE OF THE REPLAYED GAME, RETURNS FALSE AT THE END OR ON A BAD MOVE
+    fn replay_forward(&mut self) -> bool {
+        let Some(Replay {
+            games,
+            game: Some(game),
+            ..
+        }) = &self.replay
+        else {
+            return false;
+        };
+
+        let ply = self.history.len();
+        let Some(san) = games[*game].moves.get(ply) else {
+            return false;
+        };
+
+        match san_to_move(&self.board_str, &self.valid_moves, san) {
+            Some((from, to, promotion)) => self.apply_move(from, to, promotion),
+            None => {
+                log!(LogLevel::Error, "Could not play move {}: {}", ply + 1, san);
+                false
+            }
+        }
+    }
+
+    // SHOW THE POSITION AFTER PLY MOVES, REPLAYS JUMP THERE INSTEAD
+    fn view_ply(&mut self, ply: usize) {
+        if self.replay.is_some() {
+            self.replay_to(ply);
+            return;
+        }
+
+        self.selected_piece = None;
+        self.dragging = false;
+
+        if ply >= self.history.len() {
+            self.view = None;
+            return;
+        }
+
+        let mut board = Board::new();
+        board.init_board();
+
+        if let Some(fen) = &self.start_fen {
+            load_fen(&mut board, fen).expect("Start FEN was validated before the game");
+        }
+
+        for m in &self.history[..ply] {
+            move_piece(&mut board, m.from, m.to, m.promotion);
         }
+
+        let info = board.get_boardinfo();
+        let board_str = invert_boardstr((info[7..71]).to_string());
+        self.view = Some(View {
+            ply,
+            board,
+            board_str,
+        });
+    }
+
+    // FIRST LINE OF THE MOVE LIST ON SCREEN
+    fn first_move_row(&self, rows: usize) -> usize {
+        let last = rows.saturating_sub(self.layout.move_rows());
+        self.move_scroll.unwrap_or(last).min(last)
+    }
+
+    fn replay_to(&mut self, ply: usize) {
+        self.reset_board();
+        while self.history.len() < ply && self.replay_forward() {}
     }
 
     fn update_board(&mut self) {
+        let turn_before = self.turn;
         let info = self.board.get_boardinfo();
-        self.turn = if &info[2..3] == "W" {
-            Color::White
-        } else {
-            Color::Black
-        };
+        self.turn = turn_from_boardinfo(&info);
         self.board_str = invert_boardstr((info[7..71]).to_string());
 
+        // IN A HOT-SEAT GAME WE PLAY WHOEVER IS TO MOVE
+        if self.local {
+            self.my_color = self.turn;
+        }
+
+        if self.turn != turn_before {
+            if let Some(clock) = &mut self.clock {
+                clock.add_increment(turn_before);
+            }
+        }
+
         self.valid_moves = generate_valid_moves(&mut self.board);
 
         let is_over = is_over(&mut self.board);
 
         match is_over {
             0 => (),
-            1 => self.status = Status::Checkmate,
-            2 => self.status = Status::Stalemate,
-            3 => self.status = Status::ThreefoldRepetition,
-            4 => self.status = Status::FiftyMoveRule,
+            1 => self.end_game(Status::Checkmate),
+            2 => self.end_game(Status::Stalemate),
+            3 => self.end_game(Status::ThreefoldRepetition),
+            4 => self.end_game(Status::FiftyMoveRule),
             _ => (),
         }
     }
 
-    fn move_myself(&mut self, idx: usize) {
-        let mv = net::Move {
-            from: pos_int_to_tuple(self.selected_piece.unwrap()),
-            to: pos_int_to_tuple(idx),
-            offer_draw: false,
-            promotion: None,
-            forfeit: false,
-        };
+    // APPLY A MOVE AND RECORD IT, RETURNS FALSE IF THE BOARD REJECTED IT
+    fn apply_move(
+        &mut self,
+        from: usize,
+        to: usize,
+        promotion: Option<net::PromotionPiece>,
+    ) -> bool {
+        let san = move_to_san(&self.board_str, &self.valid_moves, from, to, promotion);
+        let board_before = self.board_str.clone();
+        let turn_before = self.turn;
+        let was_active = self.status == Status::Active;
+
+        move_piece(&mut self.board, from, to, promotion);
+        self.update_board();
+
+        if self.turn == turn_before {
+            return false;
+        }
 
-        self.conn.send(mv);
+        self.arrows.clear();
+        self.circles.clear();
 
-        let ack = self.conn.receive_skibidi::<net::Ack>();
+        let duration = Duration::from_millis(self.config.animation);
+        self.animation =
+            (!duration.is_zero()).then(|| Animation::new(&board_before, from, to, duration));
 
-        if ack.ok {
-            move_piece(&mut self.board, self.selected_piece.unwrap(), idx);
-            self.update_board();
-        } else {
-            println!("Invalid move");
+        let san = san + check_suffix(&mut self.board, &self.board_str, self.turn);
+        self.history.push(PlayedMove {
+            from,
+            to,
+            promotion,
+            san,
+        });
+        self.notify_opponent();
+
+        if was_active && self.status != Status::Active && self.replay.is_none() && !self.analysis {
+            self.save_pgn();
         }
+
+        true
     }
 
-    fn move_opp(&mut self, from: (u8, u8), to: (u8, u8)) {
-        move_piece(
-            &mut self.board,
-            pos_tuple_to_int(from),
-            pos_tuple_to_int(to),
-        );
-        self.update_board();
+    fn winner(&self) -> Option<Color> {
+        match self.status {
+            Status::Checkmate => Some(opposite_color(self.turn)),
+            Status::Resignation(color) | Status::IllegalMoves(color) => Some(opposite_color(color)),
+            Status::Timeout => self
+                .clock
+                .as_ref()
+                .and_then(|clock| clock.flagged())
+                .map(opposite_color),
+            _ => None,
+        }
     }
-}
 
-impl EventHandler<ggez::GameError> for Chess {
-    fn update(&mut self, _ctx: &mut Context) -> GameResult {
-        if self.my_color != self.turn {
-            let m: Option<net::Move> = self.conn.receive();
+    fn status_text(&self) -> String {
+        match self.status {
+            Status::Resignation(color) => format!("{:?} resigned", color),
+            Status::IllegalMoves(_) => "Illegal moves".to_string(),
+            status => format!("{:?}", status),
+        }
+    }
 
-            if m.is_none() {
-                return Ok(());
-            }
+    // NOTHING IN FLIGHT SURVIVES THE END OF THE GAME, THE OPPONENT STOPS ACKING
+    fn end_game(&mut self, status: Status) {
+        self.status = status;
+        self.selected_piece = None;
+        self.pending_promotion = None;
+        self.pending_move = None;
+        self.offer_draw = false;
+        self.draw_offered = false;
+
+        if let Some(opponent) = &mut self.opponent {
+            opponent.game_ended();
+        }
+    }
 
-            let m = m.unwrap();
+    fn resign(&mut self, color: Color) {
+        log!(LogLevel::Info, "{:?} resigned", color);
 
-            println!("Received move: {:?}", m);
+        self.end_game(Status::Resignation(color));
+        self.save_pgn();
+    }
 
-            let turn_before = self.turn.clone();
+    fn agree_draw(&mut self) {
+        log!(LogLevel::Info, "Draw agreed");
 
-            self.move_opp(m.from, m.to);
-            self.update_board();
+        self.end_game(Status::DrawAgreed);
+        self.save_pgn();
+    }
 
-            if turn_before == self.turn {
-                // Invalid move
-                self.conn.send(net::Ack {
-                    ok: false,
-                    end_state: None,
-                });
-            } else {
-                self.conn.send(net::Ack {
-                    ok: true,
-                    end_state: None,
-                });
-            }
+    // A FINISHED NETWORK GAME CAN NOT BE RESTARTED, THE PEER WOULD NEVER ACK
+    fn shows_reset_button(&self) -> bool {
+        self.replay.is_none()
+            && (self.status == Status::Active
+                || self.analysis
+                || self.opponent.as_ref().is_none_or(|o| o.can_restart()))
+    }
+
+    fn answer_draw(&mut self, accept: bool) {
+        self.incoming_offer = false;
+
+        if let Some(opponent) = &mut self.opponent {
+            opponent.ack(net::Ack {
+                ok: true,
+                end_state: match accept {
+                    true => Some(net::GameState::Draw),
+                    false => end_state(self.status),
+                },
+            });
         }
 
-        Ok(())
+        if accept {
+            self.agree_draw();
+        }
     }
 
-    fn draw(&mut self, ctx: &mut Context) -> GameResult {
-        let mut canvas = graphics::Canvas::from_frame(ctx, graphics::Color::BLACK);
-        let selected_piece_idx = self.selected_piece.unwrap_or(69); // nice
+    // COMPARE THE OPPONENT'S VIEW OF THE GAME WITH OURS
+    fn check_end_state(&mut self, theirs: Option<net::GameState>) {
+        if !self
+            .opponent
+            .as_ref()
+            .is_some_and(|opponent| opponent.reports_end_state())
+        {
+            return;
+        }
 
-        // START DRAW GRID
-        let dst = Vec2::new(100.0, 100.0);
-        canvas.draw(&self.grid, graphics::DrawParam::new().dest(dst));
+        let ours = end_state(self.status);
 
-        // LOOP THROUGH BOARD STRING AND DRAW PIECES
-        for (i, c) in self.board_str.chars().enumerate() {
-            // START CALCULATE POSITION
-            let x = (i % 8) as f32 * TILE_SIZE + OFFSET;
-            let y = (i / 8) as f32 * TILE_SIZE + OFFSET;
+        if !same_end_state(theirs, ours) {
+            let warning = format!("Opponent reports {:?}, we see {:?}", theirs, ours);
+            log!(LogLevel::Warn, "Warning: {}", warning);
+            self.warning = Some(warning);
+        }
+    }
 
-            let mut piece_dst = Vec2::new(x, y);
+    fn illegal_move(&mut self, reason: &str) {
+        log!(LogLevel::Warn, "Illegal move from opponent: {}", reason);
 
-            // START HANDLE SELECTED PIECE
+        self.illegal_moves += 1;
+        if self.illegal_moves < ILLEGAL_MOVE_LIMIT {
+            return;
+        }
+
+        match self.illegal_policy {
+            IllegalMovePolicy::Warn => {
+                self.warning = Some(format!(
+                    "Opponent sent {} illegal moves in a row",
+                    self.illegal_moves
+                ));
+            }
+            IllegalMovePolicy::Ignore => (),
+            IllegalMovePolicy::End => {
+                self.end_game(Status::IllegalMoves(opposite_color(self.my_color)));
+                self.save_pgn();
+            }
+        }
+    }
+
+    fn forfeit(&mut self) {
+        if let Some(opponent) = &mut self.opponent {
+            opponent.send_move(net::Move {
+                from: (0, 0),
+                to: (0, 0),
+                offer_draw: false,
+                promotion: None,
+                forfeit: true,
+            });
+        }
+
+        self.resign(self.my_color);
+    }
+
+    fn save_pgn(&self) {
+        let pgn = write_pgn(
+            &self.white_name,
+            &self.black_name,
+            pgn_result(self.status, self.winner()),
+            self.start_fen.as_deref(),
+            &self.history,
+        );
+
+        match std::fs::write(&self.pgn_path, pgn) {
+            Ok(_) => log!(LogLevel::Info, "Saved game to {}", self.pgn_path),
+            Err(e) => log!(
+                LogLevel::Error,
+                "Could not save game to {}: {}",
+                self.pgn_path,
+                e
+            ),
+        }
+    }
+
+    fn try_move(&mut self, from: usize, to: usize) {
+        if is_promotion(&self.board_str, from, to) {
+            // WAIT FOR THE PLAYER TO PICK A PIECE
+            self.pending_promotion = Some((from, to));
+        } else {
+            self.move_myself(from, to, None);
+        }
+    }
+
+    fn move_myself(&mut self, from: usize, to: usize, promotion: Option<net::PromotionPiece>) {
+        let offer_draw = std::mem::take(&mut self.offer_draw);
+
+        let mv = net::Move {
+            from: pos_int_to_tuple(from),
+            to: pos_int_to_tuple(to),
+            offer_draw,
+            promotion,
+            forfeit: false,
+        };
+
+        match &mut self.opponent {
+            Some(opponent) => {
+                // APPLIED ONCE THE OPPONENT ACKS IT IN UPDATE
+                opponent.send_move(mv);
+                self.pending_move = Some((from, to, promotion));
+                self.draw_offered = offer_draw;
+            }
+            None => {
+                // THE OTHER PLAYER ANSWERS AT THE SAME SCREEN
+                if self.apply_move(from, to, promotion) && self.status == Status::Active {
+                    self.incoming_offer = offer_draw;
+                }
+            }
+        }
+    }
+
+    // VALIDATE THE OPPONENT'S MOVE BEFORE IT TOUCHES THE BOARD
+    fn move_opp(
+        &mut self,
+        from: (u8, u8),
+        to: (u8, u8),
+        promotion: Option<net::PromotionPiece>,
+    ) -> Result<(), String> {
+        if [from.0, from.1, to.0, to.1].iter().any(|&c| c > 7) {
+            return Err(format!("{:?} -> {:?} is off the board", from, to));
+        }
+
+        let from = pos_tuple_to_int(from);
+        let to = pos_tuple_to_int(to);
+
+        validate_move(
+            &self.board_str,
+            &self.valid_moves,
+            self.turn,
+            from,
+            to,
+            promotion,
+        )?;
+
+        match self.apply_move(from, to, promotion) {
+            true => Ok(()),
+            false => Err("the board refused it".to_string()),
+        }
+    }
+}
+
+impl EventHandler<ggez::GameError> for Chess {
+    fn update(&mut self, ctx: &mut Context) -> GameResult {
+        // MOVE ANIMATIONS FOLLOW THE FRAME TIME
+        if let Some(animation) = &mut self.animation {
+            animation.elapsed += ctx.time.delta();
+            if animation.finished() {
+                self.animation = None;
+            }
+        }
+
+        // CONTINUE FROM THE SERVER'S POSITION AFTER A RECONNECT
+        if let Some(fen) = self.opponent.as_mut().and_then(|o| o.poll_resync()) {
+            self.pending_move = None;
+
+            let current = position_fen(
+                &self.board_str,
+                self.turn,
+                self.start_fen.as_deref(),
+                &self.history,
+            );
+
+            if fen != current && validate_fen(&fen).is_ok() {
+                log!(
+                    LogLevel::Warn,
+                    "Position differs from the server, continuing from {}",
+                    fen
+                );
+                self.start_fen = Some(fen);
+
+                // THE GAME GOES ON, SO THE CLOCK KEEPS ITS TIME
+                let clock = self.clock.take();
+                self.reset_board();
+                self.clock = clock;
+            }
+        }
+
+        // EVERYTHING WAITS WHILE THE OPPONENT IS UNREACHABLE
+        if self.opponent.as_ref().and_then(|o| o.problem()).is_some() {
+            return Ok(());
+        }
+
+        // A MESSAGE WE COULD NOT READ IS SHOWN, AND NACKED IF IT WAS PROBABLY THEIR MOVE
+        if let Some(opponent) = &mut self.opponent {
+            if let Some(e) = opponent.poll_error() {
+                self.warning = Some(format!("Could not read the opponent's message: {}", e));
+
+                if self.status == Status::Active && self.turn != self.my_color {
+                    opponent.ack(net::Ack {
+                        ok: false,
+                        end_state: None,
+                    });
+                }
+            }
+        }
+
+        // THE OPPONENT MAY GIVE UP AT ANY TIME
+        if self.status == Status::Active {
+            if let Some(opponent) = &mut self.opponent {
+                if opponent.poll_forfeit() {
+                    self.resign(opposite_color(self.my_color));
+                }
+            }
+        }
+
+        // RUN THE CLOCK OF THE SIDE TO MOVE
+        if self.status == Status::Active {
+            if let Some(clock) = &mut self.clock {
+                clock.tick(self.turn, ctx.time.delta());
+
+                if clock.flagged().is_some() {
+                    self.end_game(Status::Timeout);
+                    self.save_pgn();
+                }
+            }
+        }
+
+        // APPLY OUR MOVE WHEN THE OPPONENT ACCEPTS IT
+        if let Some((from, to, promotion)) = self.pending_move {
+            let ack = self
+                .opponent
+                .as_mut()
+                .and_then(|opponent| opponent.poll_ack());
+
+            match ack {
+                Some(ack) if ack.ok => {
+                    self.pending_move = None;
+                    self.apply_move(from, to, promotion);
+
+                    // THE GHOST ALREADY SHOWED WHERE THE PIECE WENT
+                    self.animation = None;
+
+                    if std::mem::take(&mut self.draw_offered) && self.status == Status::Active {
+                        match ack.end_state {
+                            Some(net::GameState::Draw) => self.agree_draw(),
+                            _ => log!(LogLevel::Info, "Draw offer declined"),
+                        }
+                    }
+
+                    self.check_end_state(ack.end_state);
+                }
+                Some(_) => {
+                    self.pending_move = None;
+                    self.draw_offered = false;
+                    log!(LogLevel::Warn, "Invalid move");
+                }
+                None => return Ok(()),
+            }
+        }
+
+        if self.status != Status::Active || self.incoming_offer {
+            return Ok(());
+        }
+
+        if self.my_color != self.turn {
+            let Some(opponent) = &mut self.opponent else {
+                return Ok(());
+            };
+
+            let m = opponent.poll_move();
+
+            if m.is_none() {
+                return Ok(());
+            }
+
+            let m = m.unwrap();
+
+            log!(LogLevel::Debug, "Received move: {:?}", m);
+
+            if m.forfeit {
+                opponent.ack(net::Ack {
+                    ok: true,
+                    end_state: None,
+                });
+                self.resign(opposite_color(self.my_color));
+                return Ok(());
+            }
+
+            let ok = match self.move_opp(m.from, m.to, m.promotion) {
+                Ok(()) => {
+                    self.illegal_moves = 0;
+                    true
+                }
+                Err(e) => {
+                    self.illegal_move(&e);
+                    false
+                }
+            };
+
+            // HOLD THE ACK UNTIL THE PLAYER ANSWERS THE DRAW OFFER
+            if ok && m.offer_draw && self.status == Status::Active {
+                self.incoming_offer = true;
+                return Ok(());
+            }
+
+            if let Some(opponent) = &mut self.opponent {
+                opponent.ack(net::Ack {
+                    ok,
+                    end_state: end_state(self.status),
+                });
+            }
+        }
+
+        Ok(())
+    }
+
+    fn draw(&mut self, ctx: &mut Context) -> GameResult {
+        let mut canvas = graphics::Canvas::from_frame(ctx, self.theme().background_color());
+        let selected_piece_idx = self.selected_piece.unwrap_or(69); // nice
+        let layout = self.layout;
+        let tile = layout.tile;
+        let center = layout.center();
+        let accent = self.config.accent_color();
+
+        // START DRAW GRID
+        let dst = Vec2::new(layout.left, layout.top);
+        canvas.draw(&self.meshes.grid, graphics::DrawParam::new().dest(dst));
+
+        // AN EARLIER POSITION FROM THE MOVE LIST REPLACES THE LIVE ONE
+        let viewing = self.view.is_some();
+        let (board_str, shown_ply) = match &self.view {
+            Some(view) => (view.board_str.clone(), view.ply),
+            None => (self.board_str.clone(), self.history.len()),
+        };
+        let last_move = self.history[..shown_ply].last().map(|m| (m.from, m.to));
+        let animation = self.animation.as_ref().filter(|_| !viewing);
+
+        // LOOP THROUGH BOARD STRING AND DRAW PIECES
+        for (i, c) in board_str.chars().enumerate() {
+            // START CALCULATE POSITION
+            let Vec2 { x, y } = layout.square_pos(i);
+
+            // TINT THE SQUARES OF THE LAST MOVE UNDER THE PIECES
+            if last_move.is_some_and(|(from, to)| from == i || to == i) {
+                canvas.draw(
+                    &self.meshes.last_move,
+                    graphics::DrawParam::new().dest(Vec2::new(x, y)),
+                );
+            }
+
+            let mut piece_dst = Vec2::new(x, y);
+
+            // START HANDLE SELECTED PIECE
             if selected_piece_idx == i {
                 // DRAW SELECTION BORDER AROUND PIECE
                 let dest = Vec2::new(x, y);
-                canvas.draw(&self.piece_mesh, graphics::DrawParam::new().dest(dest));
+                canvas.draw(
+                    &self.meshes.selection,
+                    graphics::DrawParam::new().dest(dest),
+                );
 
                 // IF DRAGGING, MOVE PIECE TO MOUSE POSITION
                 if self.dragging {
                     piece_dst = Vec2::new(
-                        self.mouse_pos.0 - (TILE_SIZE / 2.0),
-                        self.mouse_pos.1 - (TILE_SIZE / 2.0),
+                        self.mouse_pos.0 - (tile / 2.0),
+                        self.mouse_pos.1 - (tile / 2.0),
                     );
                 }
             }
@@ -353,15 +1299,35 @@ impl EventHandler<ggez::GameError> for Chess {
                 .iter()
                 .find(|(piece, _)| piece == &c.to_string());
 
+            // PIECES STILL SLIDING IN ARE DRAWN AFTER THE BOARD
+            let animated = animation.is_some_and(|a| a.covers(i));
+
             if let Some((_, img)) = img {
-                canvas.draw(img, graphics::DrawParam::new().dest(piece_dst));
+                let scale = layout.piece_scale(img);
+                if !animated {
+                    canvas.draw(img, graphics::DrawParam::new().dest(piece_dst).scale(scale));
+                }
+
+                // GHOST OF OUR MOVE ON ITS TARGET UNTIL THE OPPONENT ACKS IT
+                if let Some((from, to, _)) = self.pending_move.filter(|_| !viewing) {
+                    if from == i {
+                        let ghost_dst = layout.square_pos(to);
+                        canvas.draw(
+                            img,
+                            graphics::DrawParam::new()
+                                .dest(ghost_dst)
+                                .scale(scale)
+                                .color(graphics::Color::from_rgba(255, 255, 255, 120)),
+                        );
+                    }
+                }
             }
 
             // DRAW VALID MOVES CIRCLE
             if selected_piece_idx != 69 && self.valid_moves[selected_piece_idx].contains(&i) {
-                let dest = Vec2::new(x + TILE_SIZE / 2.0, y + TILE_SIZE / 2.0);
+                let dest = Vec2::new(x + tile / 2.0, y + tile / 2.0);
                 canvas.draw(
-                    &self.valid_circle_mesh,
+                    &self.meshes.valid_circle,
                     graphics::DrawParam::new().dest(dest),
                 );
             }
@@ -369,8 +1335,9 @@ impl EventHandler<ggez::GameError> for Chess {
             // DRAW NUMERS BELOW BOARD
             if i % 8 == 0 {
                 let mut text = graphics::Text::new(format!("{}", 8 - i / 8));
-                text.set_scale(graphics::PxScale::from(40.0));
-                let text_dest = Vec2::new(54.0, y + TILE_SIZE / 2.0 - (TILE_SIZE / 5.0));
+                text.set_scale(graphics::PxScale::from(tile * 0.4));
+                text.set_layout(graphics::TextLayout::center());
+                let text_dest = Vec2::new(layout.left - layout.margin * 0.34, y + tile / 2.0);
 
                 canvas.draw(&text, graphics::DrawParam::new().dest(text_dest));
             }
@@ -378,57 +1345,351 @@ impl EventHandler<ggez::GameError> for Chess {
             // DRAW LETTERS BESIDE BOARD
             if i < 8 {
                 let mut text = graphics::Text::new(format!("{}", (65 + (i % 8)) as u8 as char));
-                text.set_scale(graphics::PxScale::from(40.0));
-                let text_dest = Vec2::new(x + TILE_SIZE / 2.0 - (TILE_SIZE / 8.0), 918.0);
+                text.set_scale(graphics::PxScale::from(tile * 0.4));
+                text.set_layout(graphics::TextLayout::center());
+                let text_dest = Vec2::new(x + tile / 2.0, layout.bottom() + tile * 0.38);
 
                 canvas.draw(&text, graphics::DrawParam::new().dest(text_dest));
             }
 
-            let k1 = i % 8;
-            let k2 = 8 - i / 8 - 1;
-            let k = (k1 + k2 * 8) as i64;
+            let board = match &mut self.view {
+                Some(view) => &mut view.board,
+                None => &mut self.board,
+            };
 
-            if (c == 'K' || c == 'k') && in_check(&mut self.board, k) {
-                let dest = Vec2::new(x + TILE_SIZE / 2.0, y + TILE_SIZE / 2.0);
+            if (c == 'K' || c == 'k') && in_check(board, board_square(i)) {
+                let dest = Vec2::new(x + tile / 2.0, y + tile / 2.0);
                 canvas.draw(
-                    &self.check_circle_mesh,
+                    &self.meshes.check_circle,
                     graphics::DrawParam::new().dest(dest),
                 );
             }
         }
 
+        // DRAW THE LAST MOVE SLIDING IN AND ITS CAPTURE FADING OUT
+        if let Some(animation) = animation {
+            let t = animation.progress();
+            let image = |c: char| {
+                self.piece_images
+                    .iter()
+                    .find(|(piece, _)| piece == &c.to_string())
+                    .map(|(_, img)| img)
+            };
+
+            if let Some((c, square)) = animation.captured {
+                if let Some(img) = image(c) {
+                    canvas.draw(
+                        img,
+                        graphics::DrawParam::new()
+                            .dest(layout.square_pos(square))
+                            .scale(layout.piece_scale(img))
+                            .color(graphics::Color::new(1.0, 1.0, 1.0, 1.0 - t)),
+                    );
+                }
+            }
+
+            for &(c, from, to) in &animation.moved {
+                if let Some(img) = image(c) {
+                    let dest = layout.square_pos(from).lerp(layout.square_pos(to), t);
+                    canvas.draw(
+                        img,
+                        graphics::DrawParam::new()
+                            .dest(dest)
+                            .scale(layout.piece_scale(img)),
+                    );
+                }
+            }
+        }
+
+        // DRAW CIRCLES AND ARROWS ON TOP OF THE PIECES
+        for &square in &self.circles {
+            let dest = layout.square_pos(square) + Vec2::splat(tile / 2.0);
+            canvas.draw(
+                &self.meshes.annotation_circle,
+                graphics::DrawParam::new().dest(dest),
+            );
+        }
+
+        if !self.arrows.is_empty() {
+            let color = self.theme().annotation_color();
+            let arrows = build_arrows(ctx, &layout, &self.arrows, color)?;
+            canvas.draw(&arrows, graphics::DrawParam::new());
+        }
+
         // DRAW TURN TEXT
-        let mut text = graphics::Text::new(format!(
-            "Turn: {:?}. You are: {:?}",
-            self.turn, self.my_color
-        ));
+        let header = match &self.replay {
+            Some(Replay {
+                games,
+                game: Some(game),
+                ..
+            }) => format!("Move {}/{}", self.history.len(), games[*game].moves.len()),
+            Some(_) => "Choose a game".to_string(),
+            None if self.analysis => format!("Analysis, {:?} to move", self.turn),
+            None if self.local => format!("Turn: {:?}", self.turn),
+            None if self.pending_move.is_some() => "Waiting for opponent...".to_string(),
+            None => format!("Turn: {:?}. You are: {:?}", self.turn, self.my_color),
+        };
+        let mut text = graphics::Text::new(header);
         text.set_scale(graphics::PxScale::from(40.0));
         text.set_layout(graphics::TextLayout::center());
-        let text_dest = Vec2::new(500.0, 50.0);
+        let text_dest = Vec2::new(center.x, layout.top / 2.0);
         canvas.draw(&text, graphics::DrawParam::new().dest(text_dest));
 
+        // DRAW CONNECTION PROBLEMS BELOW TURN TEXT
+        if let Some(problem) = self.opponent.as_ref().and_then(|o| o.problem()) {
+            let mut text = graphics::Text::new(problem);
+            text.set_scale(graphics::PxScale::from(24.0));
+            text.set_layout(graphics::TextLayout::center());
+            let text_dest = Vec2::new(center.x, layout.top * 0.85);
+            canvas.draw(
+                &text,
+                graphics::DrawParam::new()
+                    .dest(text_dest)
+                    .color(graphics::Color::RED),
+            );
+        }
+
+        // DRAW END STATE DISAGREEMENT BELOW BOARD
+        if let Some(warning) = &self.warning {
+            let mut text = graphics::Text::new(warning.as_str());
+            text.set_scale(graphics::PxScale::from(24.0));
+            text.set_layout(graphics::TextLayout::center());
+            let text_dest = Vec2::new(center.x, layout.bottom() + layout.margin * 0.75);
+            canvas.draw(
+                &text,
+                graphics::DrawParam::new()
+                    .dest(text_dest)
+                    .color(graphics::Color::YELLOW),
+            );
+        }
+
+        // DRAW NAMES BESIDE BOARD, ABOVE AND BELOW THE CLOCKS
+        let top_color = layout.top_color();
+        let names = [
+            (Color::Black, &self.black_name),
+            (Color::White, &self.white_name),
+        ];
+        for (color, name) in names {
+            let y = if color == top_color {
+                layout.top
+            } else {
+                layout.bottom() - 20.0
+            };
+
+            let short: String = if name.chars().count() > 10 {
+                name.chars().take(8).chain("..".chars()).collect()
+            } else {
+                name.clone()
+            };
+
+            let mut text = graphics::Text::new(short);
+            text.set_scale(graphics::PxScale::from(20.0));
+            text.set_layout(graphics::TextLayout::center());
+            let text_dest = Vec2::new(layout.side_x(), y);
+            canvas.draw(&text, graphics::DrawParam::new().dest(text_dest));
+        }
+
+        // DRAW CLOCKS BESIDE BOARD
+        if let Some(clock) = &self.clock {
+            for color in [Color::Black, Color::White] {
+                let y = if color == top_color {
+                    layout.top + 30.0
+                } else {
+                    layout.bottom() - 50.0
+                };
+
+                let mut text = graphics::Text::new(format_time(clock.remaining(color)));
+                text.set_scale(graphics::PxScale::from(30.0));
+                text.set_layout(graphics::TextLayout::center());
+                let text_dest = Vec2::new(layout.side_x(), y);
+
+                let text_color = if color == self.turn && self.status == Status::Active {
+                    accent
+                } else {
+                    graphics::Color::WHITE
+                };
+
+                canvas.draw(
+                    &text,
+                    graphics::DrawParam::new().dest(text_dest).color(text_color),
+                );
+            }
+        }
+
+        // DRAW MOVE LIST BESIDE THE BOARD
+        canvas.draw(&self.meshes.move_panel, graphics::DrawParam::new());
+
+        let rows = numbered_moves(self.start_fen.as_deref(), self.history.len());
+        let first = self.first_move_row(rows.len());
+        for (row, (number, white, black)) in
+            rows.iter().skip(first).take(layout.move_rows()).enumerate()
+        {
+            let y = layout.move_row_y(row);
+
+            let mut text = graphics::Text::new(format!("{}.", number));
+            text.set_scale(graphics::PxScale::from(18.0));
+            let dest = Vec2::new(layout.move_panel().x + 8.0, y);
+            canvas.draw(
+                &text,
+                graphics::DrawParam::new()
+                    .dest(dest)
+                    .color(graphics::Color::from_rgb(150, 150, 150)),
+            );
+
+            for (column, idx) in [white, black].into_iter().enumerate() {
+                let san = match idx {
+                    Some(idx) => self.history[*idx].san.as_str(),
+                    None if column == 0 => "...",
+                    None => continue,
+                };
+
+                // THE MOVE THAT LED TO THE POSITION ON THE BOARD
+                let text_color = if *idx == shown_ply.checked_sub(1) {
+                    accent
+                } else {
+                    graphics::Color::WHITE
+                };
+
+                let mut text = graphics::Text::new(san);
+                text.set_scale(graphics::PxScale::from(18.0));
+                let dest = Vec2::new(layout.move_column_x(column), y);
+                canvas.draw(
+                    &text,
+                    graphics::DrawParam::new().dest(dest).color(text_color),
+                );
+            }
+        }
+
+        // DRAW BACK TO LIVE BUTTON WHILE LOOKING AT AN EARLIER POSITION
+        if viewing {
+            let button = layout.live_button();
+            canvas.draw(&self.meshes.live_button, graphics::DrawParam::new());
+
+            let mut text = graphics::Text::new("Back to live");
+            text.set_scale(graphics::PxScale::from(20.0));
+            text.set_layout(graphics::TextLayout::center());
+            let text_dest = Vec2::new(button.x + button.w / 2.0, button.y + button.h / 2.0);
+            canvas.draw(
+                &text,
+                graphics::DrawParam::new()
+                    .dest(text_dest)
+                    .color(graphics::Color::BLACK),
+            );
+        }
+
         // DRAW RESET BUTTON
-        canvas.draw(&self.reset_button_mesh, graphics::DrawParam::new());
+        if self.shows_reset_button() {
+            let button = layout.reset_button();
+            canvas.draw(&self.meshes.reset_button, graphics::DrawParam::new());
+
+            let (label, label_color) = if self.analysis {
+                ("Reset", graphics::Color::BLACK)
+            } else if self.status != Status::Active {
+                ("New game", graphics::Color::BLACK)
+            } else if self.confirm_forfeit {
+                ("Resign?", graphics::Color::RED)
+            } else {
+                ("Forfeit", graphics::Color::BLACK)
+            };
 
-        let mut reset_text = graphics::Text::new("Forfeit");
-        let reset_text_dest = Vec2::new(
-            self.reset_button_rect.x + 37.0,
-            self.reset_button_rect.y + 3.0,
-        );
-        reset_text.set_scale(graphics::PxScale::from(30.0));
-        canvas.draw(
-            &reset_text,
-            graphics::DrawParam::new()
-                .dest(reset_text_dest)
-                .color(graphics::Color::BLACK),
-        );
+            let mut reset_text = graphics::Text::new(label);
+            let reset_text_dest = Vec2::new(button.x + button.w / 2.0, button.y + 3.0);
+            reset_text.set_scale(graphics::PxScale::from(30.0));
+            reset_text.set_layout(graphics::TextLayout {
+                h_align: graphics::TextAlign::Middle,
+                v_align: graphics::TextAlign::Begin,
+            });
+            canvas.draw(
+                &reset_text,
+                graphics::DrawParam::new()
+                    .dest(reset_text_dest)
+                    .color(label_color),
+            );
+
+            // CONFIRMATION HINT NEXT TO THE BUTTON
+            if self.confirm_forfeit {
+                let mut text =
+                    graphics::Text::new("Click again to resign, anywhere else to cancel");
+                text.set_scale(graphics::PxScale::from(20.0));
+                let text_dest = Vec2::new(button.x, button.y + button.h + 5.0);
+                canvas.draw(&text, graphics::DrawParam::new().dest(text_dest));
+            }
+        }
+
+        // DRAW OFFER DRAW BUTTON
+        if self.replay.is_none() && !self.analysis && self.status == Status::Active {
+            let button = layout.draw_button();
+            canvas.draw(&self.meshes.draw_button, graphics::DrawParam::new());
+
+            let (label, label_color) = if self.draw_offered {
+                ("Draw offered", accent)
+            } else if self.offer_draw {
+                ("With next move", accent)
+            } else {
+                ("Offer draw", graphics::Color::BLACK)
+            };
+
+            let mut text = graphics::Text::new(label);
+            text.set_scale(graphics::PxScale::from(24.0));
+            text.set_layout(graphics::TextLayout {
+                h_align: graphics::TextAlign::Middle,
+                v_align: graphics::TextAlign::Middle,
+            });
+            let text_dest = Vec2::new(button.x + button.w / 2.0, button.y + button.h / 2.0);
+            canvas.draw(
+                &text,
+                graphics::DrawParam::new()
+                    .dest(text_dest)
+                    .color(label_color),
+            );
+        }
+
+        // DRAW PROMOTION DIALOG
+        if let Some((_, to)) = self.pending_promotion {
+            for (n, piece) in PROMOTION_PIECES.iter().enumerate() {
+                let square = promotion_square(to, n);
+                let dest = layout.square_pos(square);
+                canvas.draw(
+                    &self.meshes.promotion,
+                    graphics::DrawParam::new().dest(dest),
+                );
+
+                let mut c = promotion_to_char(*piece);
+                if self.my_color == Color::White {
+                    c = c.to_ascii_lowercase();
+                }
+
+                let img = self.piece_images.iter().find(|(p, _)| p == &c.to_string());
+
+                if let Some((_, img)) = img {
+                    let scale = layout.piece_scale(img);
+                    canvas.draw(img, graphics::DrawParam::new().dest(dest).scale(scale));
+                }
+            }
+        }
 
         // DRAW STATUS TEXT
         if self.status != Status::Active {
-            let mut text = graphics::Text::new(format!("{:?}", self.status));
-            text.set_scale(graphics::PxScale::from(100.0));
+            let mut text = graphics::Text::new(self.status_text());
+            text.set_scale(graphics::PxScale::from(tile));
             text.set_layout(graphics::TextLayout::center());
-            let text_dest = Vec2::new(500.0, 500.0);
+            let text_dest = center;
+            canvas.draw(
+                &text,
+                graphics::DrawParam::new()
+                    .dest(text_dest)
+                    .color(graphics::Color::RED),
+            );
+
+            // DRAW WINNER BELOW STATUS
+            let result = match self.winner() {
+                Some(color) => format!("{:?} wins", color),
+                None => "Draw".to_string(),
+            };
+            let mut text = graphics::Text::new(result);
+            text.set_scale(graphics::PxScale::from(tile / 2.0));
+            text.set_layout(graphics::TextLayout::center());
+            let text_dest = Vec2::new(center.x, center.y + tile * 0.8);
             canvas.draw(
                 &text,
                 graphics::DrawParam::new()
@@ -437,6 +1698,129 @@ impl EventHandler<ggez::GameError> for Chess {
             );
         }
 
+        // DRAW INCOMING DRAW OFFER BANNER
+        if self.incoming_offer {
+            let banner = graphics::Mesh::new_rectangle(
+                ctx,
+                graphics::DrawMode::fill(),
+                layout.banner(),
+                graphics::Color::from_rgba(0, 0, 0, 220),
+            )?;
+            canvas.draw(&banner, graphics::DrawParam::new());
+
+            let mut text =
+                graphics::Text::new(format!("{:?} offers a draw", opposite_color(self.my_color)));
+            text.set_scale(graphics::PxScale::from(30.0));
+            text.set_layout(graphics::TextLayout::center());
+            canvas.draw(
+                &text,
+                graphics::DrawParam::new().dest(Vec2::new(center.x, center.y - 35.0)),
+            );
+
+            let buttons = [
+                (
+                    layout.accept_button(),
+                    "Accept",
+                    graphics::Color::from_rgba(60, 160, 60, 255),
+                ),
+                (
+                    layout.decline_button(),
+                    "Decline",
+                    graphics::Color::from_rgba(180, 50, 50, 255),
+                ),
+            ];
+
+            for (rect, label, color) in buttons {
+                let button =
+                    graphics::Mesh::new_rectangle(ctx, graphics::DrawMode::fill(), rect, color)?;
+                canvas.draw(&button, graphics::DrawParam::new());
+
+                let mut text = graphics::Text::new(label);
+                text.set_scale(graphics::PxScale::from(30.0));
+                text.set_layout(graphics::TextLayout::center());
+                let text_dest = Vec2::new(rect.x + rect.w / 2.0, rect.y + rect.h / 2.0);
+                canvas.draw(&text, graphics::DrawParam::new().dest(text_dest));
+            }
+        }
+
+        // DRAW GAME CHOOSER OVER THE BOARD
+        if let Some(replay) = &self.replay {
+            if replay.game.is_none() {
+                let overlay = graphics::Mesh::new_rectangle(
+                    ctx,
+                    graphics::DrawMode::fill(),
+                    layout.board_rect(),
+                    graphics::Color::from_rgba(0, 0, 0, 220),
+                )?;
+                canvas.draw(&overlay, graphics::DrawParam::new());
+
+                let first = replay.first_row(layout.menu_rows());
+                let rows = replay
+                    .games
+                    .iter()
+                    .enumerate()
+                    .skip(first)
+                    .take(layout.menu_rows());
+
+                for (row, (i, game)) in rows.enumerate() {
+                    let mut text = graphics::Text::new(format!("{}. {}", i + 1, game.title()));
+                    text.set_scale(graphics::PxScale::from(30.0));
+                    text.set_layout(graphics::TextLayout::center());
+                    let text_dest = Vec2::new(center.x, layout.menu_row_y(row));
+
+                    let text_color = if i == replay.cursor {
+                        accent
+                    } else {
+                        graphics::Color::WHITE
+                    };
+
+                    canvas.draw(
+                        &text,
+                        graphics::DrawParam::new().dest(text_dest).color(text_color),
+                    );
+                }
+            }
+        }
+
+        // DRAW SETTINGS SCREEN OVER EVERYTHING
+        if let Some(settings) = &self.settings {
+            let overlay = graphics::Mesh::new_rectangle(
+                ctx,
+                graphics::DrawMode::fill(),
+                layout.board_rect(),
+                graphics::Color::from_rgba(0, 0, 0, 230),
+            )?;
+            canvas.draw(&overlay, graphics::DrawParam::new());
+
+            let hint = "Up/Down pick, Left/Right change, type to edit, Enter saves, Esc cancels";
+            let lines = SETTINGS
+                .iter()
+                .map(|setting| (settings.label(*setting), Some(*setting)))
+                .chain([(hint.to_string(), None)]);
+
+            for (row, (label, setting)) in lines.enumerate() {
+                let mut text = graphics::Text::new(label);
+                text.set_scale(graphics::PxScale::from(if setting.is_some() {
+                    30.0
+                } else {
+                    18.0
+                }));
+                text.set_layout(graphics::TextLayout::center());
+                let text_dest = Vec2::new(center.x, layout.menu_row_y(row));
+
+                let text_color = if setting == Some(settings.setting()) {
+                    accent
+                } else {
+                    graphics::Color::WHITE
+                };
+
+                canvas.draw(
+                    &text,
+                    graphics::DrawParam::new().dest(text_dest).color(text_color),
+                );
+            }
+        }
+
         canvas.finish(ctx)
     }
 
@@ -447,13 +1831,117 @@ impl EventHandler<ggez::GameError> for Chess {
         x: f32,
         y: f32,
     ) -> GameResult {
-        if button != MouseButton::Left {
+        // THE RIGHT BUTTON DRAWS ARROWS AND CIRCLES, EVEN WHILE WAITING FOR AN ACK
+        if button == MouseButton::Right && self.settings.is_none() {
+            self.annotation_start = self.layout.square_at(x, y);
+            return Ok(());
+        }
+
+        // THE MOVE LIST WORKS EVEN WHILE WAITING FOR AN ACK
+        if button == MouseButton::Left && self.settings.is_none() {
+            if self.view.is_some() && self.layout.live_button().contains(Vec2::new(x, y)) {
+                self.view = None;
+                return Ok(());
+            }
+
+            if let Some((row, column)) = self.layout.move_at(x, y) {
+                let rows = numbered_moves(self.start_fen.as_deref(), self.history.len());
+                let first = self.first_move_row(rows.len());
+
+                if let Some(idx) = rows.get(first + row).and_then(|r| [r.1, r.2][column]) {
+                    self.view_ply(idx + 1);
+                }
+                return Ok(());
+            }
+        }
+
+        // NO NEW MOVES WHILE THE LAST ONE IS WAITING FOR AN ACK
+        if button != MouseButton::Left || self.pending_move.is_some() || self.settings.is_some() {
             return Ok(());
         }
 
-        let x2 = (x - OFFSET) as i32 / TILE_SIZE as i32;
-        let y2 = (y - OFFSET) as i32 / TILE_SIZE as i32;
-        let idx = y2 as usize * 8 + x2 as usize;
+        // PICK A GAME FROM THE GAME CHOOSER
+        if let Some(replay) = &self.replay {
+            if replay.game.is_none() {
+                if let Some(row) = self.layout.menu_row_at(y) {
+                    let game = replay.first_row(self.layout.menu_rows()) + row;
+                    if game < replay.games.len() {
+                        self.load_replay_game(game);
+                    }
+                }
+
+                return Ok(());
+            }
+        }
+
+        // NOTHING ELSE UNTIL THE DRAW OFFER IS ANSWERED
+        if self.incoming_offer {
+            if self.layout.accept_button().contains([x, y]) {
+                self.answer_draw(true);
+            } else if self.layout.decline_button().contains([x, y]) {
+                self.answer_draw(false);
+            }
+
+            return Ok(());
+        }
+
+        // FORFEIT NEEDS A SECOND CLICK TO CONFIRM, ANY OTHER CLICK CANCELS
+        let on_button = self.shows_reset_button() && self.layout.reset_button().contains([x, y]);
+
+        if on_button && (self.status != Status::Active || self.analysis) {
+            self.reset_board();
+            self.pgn_path = default_pgn_path();
+            return Ok(());
+        }
+
+        if on_button && self.confirm_forfeit {
+            self.confirm_forfeit = false;
+            self.forfeit();
+            return Ok(());
+        }
+
+        self.confirm_forfeit = on_button;
+
+        if on_button {
+            return Ok(());
+        }
+
+        // TOGGLE OFFERING A DRAW WITH OUR NEXT MOVE
+        if self.replay.is_none()
+            && !self.analysis
+            && self.status == Status::Active
+            && self.layout.draw_button().contains([x, y])
+        {
+            if !self.draw_offered {
+                self.offer_draw = !self.offer_draw;
+            }
+
+            return Ok(());
+        }
+
+        // EARLIER POSITIONS ARE ONLY FOR LOOKING
+        if self.view.is_some() {
+            return Ok(());
+        }
+
+        let square = self.layout.square_at(x, y);
+
+        // IF PROMOTION DIALOG IS OPEN, PICK PIECE OR CANCEL
+        if let Some((from, to)) = self.pending_promotion {
+            let choice =
+                (0..PROMOTION_PIECES.len()).find(|n| Some(promotion_square(to, *n)) == square);
+
+            if let Some(n) = choice {
+                self.move_myself(from, to, Some(PROMOTION_PIECES[n]));
+            }
+
+            self.pending_promotion = None;
+            return Ok(());
+        }
+
+        let Some(idx) = square else {
+            return Ok(());
+        };
 
         // GET PIECE AT MOUSE POSITION
         let piece = self.board_str.chars().nth(idx);
@@ -471,7 +1959,7 @@ impl EventHandler<ggez::GameError> for Chess {
                 && self.valid_moves[self.selected_piece.unwrap()].contains(&idx)
             {
                 // IF PIECE IS SELECTED AND POSITION IS VALID, MOVE PIECE
-                self.move_myself(idx);
+                self.try_move(self.selected_piece.unwrap(), idx);
                 self.selected_piece = None;
             } else {
                 // ELSE UNSELECT PIECE
@@ -479,14 +1967,6 @@ impl EventHandler<ggez::GameError> for Chess {
             }
         }
 
-        if self.reset_button_rect.contains([x, y]) {
-            self.board = Board::new();
-            self.board.init_board();
-            self.selected_piece = None;
-            self.status = Status::Active;
-            self.update_board();
-        }
-
         Ok(())
     }
 
@@ -497,22 +1977,39 @@ impl EventHandler<ggez::GameError> for Chess {
         x: f32,
         y: f32,
     ) -> GameResult {
-        if button != MouseButton::Left {
+        // RELEASING ON THE START SQUARE TOGGLES A CIRCLE, ANYWHERE ELSE AN ARROW
+        if button == MouseButton::Right {
+            let start = self.annotation_start.take();
+            if let (Some(from), Some(to)) = (start, self.layout.square_at(x, y)) {
+                if from == to {
+                    toggle(&mut self.circles, from);
+                } else {
+                    toggle(&mut self.arrows, (from, to));
+                }
+            }
+
             return Ok(());
         }
 
-        let x2 = (x - OFFSET) as i32 / TILE_SIZE as i32;
-        let y2 = (y - OFFSET) as i32 / TILE_SIZE as i32;
+        // NO NEW MOVES WHILE THE LAST ONE IS WAITING FOR AN ACK
+        if button != MouseButton::Left || self.pending_move.is_some() || self.view.is_some() {
+            return Ok(());
+        }
 
-        let idx = y2 as usize * 8 + x2 as usize;
+        let idx = self.layout.square_at(x, y).unwrap_or(64);
 
         // IF PIECE EXISTS
         if self.selected_piece.is_some()
             && self.valid_moves[self.selected_piece.unwrap()].contains(&idx)
         {
             // IF PIECE IS SELECTED AND POSITION IS VALID, MOVE PIECE
-            self.move_myself(idx);
+            self.try_move(self.selected_piece.unwrap(), idx);
             self.selected_piece = None;
+
+            // A DROPPED PIECE IS ALREADY WHERE IT WENT
+            if self.dragging {
+                self.animation = None;
+            }
         }
 
         self.dragging = false;
@@ -533,4 +2030,129 @@ impl EventHandler<ggez::GameError> for Chess {
         }
         Ok(())
     }
+
+    // THE WHEEL SCROLLS THE MOVE LIST WHEN THE MOUSE IS OVER IT
+    fn mouse_wheel_event(&mut self, ctx: &mut Context, _x: f32, y: f32) -> GameResult {
+        if !self.layout.move_panel().contains(ctx.mouse.position()) {
+            return Ok(());
+        }
+
+        let rows = numbered_moves(self.start_fen.as_deref(), self.history.len()).len();
+        let last = rows.saturating_sub(self.layout.move_rows());
+        let first = self.first_move_row(rows);
+
+        let first = if y > 0.0 {
+            first.saturating_sub(1)
+        } else {
+            first + 1
+        };
+        self.move_scroll = (first < last).then_some(first);
+
+        Ok(())
+    }
+
+    fn key_down_event(
+        &mut self,
+        ctx: &mut Context,
+        input: KeyInput,
+        _repeated: bool,
+    ) -> GameResult {
+        // SETTINGS SCREEN TAKES ALL KEYS WHILE OPEN
+        if let Some(settings) = &mut self.settings {
+            match input.keycode {
+                Some(KeyCode::Up) => settings.up(),
+                Some(KeyCode::Down) => settings.down(),
+                Some(KeyCode::Left) => settings.change(-1),
+                Some(KeyCode::Right) => settings.change(1),
+                Some(KeyCode::Back) => settings.backspace(),
+                Some(KeyCode::Escape) => self.settings = None,
+                Some(KeyCode::Return) => {
+                    let config = settings.draft.clone();
+                    self.settings = None;
+
+                    let file_config = changed_settings(&self.file_config, &self.config, &config);
+                    match save_config(&file_config) {
+                        Ok(()) => self.file_config = file_config,
+                        Err(e) => log!(LogLevel::Error, "Could not save settings: {}", e),
+                    }
+                    self.apply_config(ctx, config);
+                }
+                _ => (),
+            }
+
+            return Ok(());
+        }
+
+        // ESCAPE LEAVES AN EARLIER POSITION
+        if input.keycode == Some(KeyCode::Escape) && self.view.is_some() {
+            self.view = None;
+            return Ok(());
+        }
+
+        // F FLIPS THE BOARD
+        if input.keycode == Some(KeyCode::F) && input.mods.is_empty() {
+            self.layout.flipped = !self.layout.flipped;
+            return Ok(());
+        }
+
+        // CTRL+, OPENS THE SETTINGS
+        if input.keycode == Some(KeyCode::Comma) && input.mods.contains(KeyMods::CTRL) {
+            self.settings = Some(SettingsScreen::new(&self.config, piece_sets(ctx)));
+            return Ok(());
+        }
+
+        // REPLAY CONTROLS
+        if let Some(replay) = &mut self.replay {
+            if replay.game.is_none() {
+                match input.keycode {
+                    Some(KeyCode::Up) => replay.cursor = replay.cursor.saturating_sub(1),
+                    Some(KeyCode::Down) => {
+                        replay.cursor = (replay.cursor + 1).min(replay.games.len() - 1)
+                    }
+                    Some(KeyCode::Return) => {
+                        let game = replay.cursor;
+                        self.load_replay_game(game);
+                    }
+                    _ => (),
+                }
+            } else {
+                match input.keycode {
+                    Some(KeyCode::Right) => {
+                        self.replay_forward();
+                    }
+                    Some(KeyCode::Left) => self.replay_to(self.history.len().saturating_sub(1)),
+                    Some(KeyCode::Home) => self.replay_to(0),
+                    Some(KeyCode::End) => self.replay_to(usize::MAX),
+                    Some(KeyCode::Escape) if replay.games.len() > 1 => replay.game = None,
+                    _ => (),
+                }
+            }
+
+            return Ok(());
+        }
+
+        // CTRL+S SAVES THE GAME AS PGN
+        if input.keycode == Some(KeyCode::S) && input.mods.contains(KeyMods::CTRL) {
+            self.save_pgn();
+        }
+
+        Ok(())
+    }
+
+    // THE BOARD FOLLOWS THE WINDOW SIZE
+    fn resize_event(&mut self, ctx: &mut Context, width: f32, height: f32) -> GameResult {
+        self.config.window = clamp_window((width, height));
+        let layout = Layout::new(self.config.window, self.config.margin, self.layout.flipped);
+        self.set_layout(ctx, layout, false);
+
+        Ok(())
+    }
+
+    fn text_input_event(&mut self, _ctx: &mut Context, character: char) -> GameResult {
+        if let Some(settings) = &mut self.settings {
+            settings.type_char(character);
+        }
+
+        Ok(())
+    }
 }
@@ -1,10 +1,81 @@
 use crate::*;
-use std::io::{Read, Write};
-use std::net::{TcpListener, TcpStream};
+use std::collections::VecDeque;
+use std::io::{self, Read, Write};
+use std::net::{Shutdown, TcpListener, TcpStream};
+use std::sync::mpsc::{self, Receiver, Sender};
 use std::time::Duration;
 
+// Give up on a message that is still incomplete after this many bytes
+const MAX_FRAME: usize = 64 * 1024;
+
+#[derive(Debug)]
+pub enum Message {
+    Start(net::Start),
+    Move(net::Move),
+    Ack(net::Ack),
+}
+
+#[derive(Debug)]
+pub enum NetError {
+    // The bytes are not MessagePack, the rest of the buffer is dropped
+    InvalidFrame(u8),
+    // No complete message within MAX_FRAME bytes
+    FrameTooLarge,
+    // A complete message that is none of the protocol messages
+    Decode(String),
+    // The peer closed the connection or it broke
+    Disconnected,
+}
+
+impl std::fmt::Display for NetError {
+    fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+        match self {
+            NetError::InvalidFrame(marker) => write!(f, "invalid message marker {:#04x}", marker),
+            NetError::FrameTooLarge => write!(f, "message larger than {} bytes", MAX_FRAME),
+            NetError::Decode(e) => write!(f, "could not decode message: {}", e),
+            NetError::Disconnected => write!(f, "connection closed"),
+        }
+    }
+}
+
 pub struct Connection {
     pub stream: TcpStream,
+    // LAST MESSAGE THAT COULD NOT BE READ, UNTIL THE GAME TAKES IT
+    last_error: Option<NetError>,
+    role: ConnectionType,
+    addr: String,
+    messages: Receiver<Result<Message, NetError>>,
+    inbox: VecDeque<Message>,
+    connected: bool,
+    // OUR START FROM THE HANDSHAKE, SENT AGAIN WHEN RECONNECTING
+    own_start: Option<net::Start>,
+    fen: String,
+    reconnecting: Option<Receiver<(TcpStream, net::Start)>>,
+    resync: Option<String>,
+    // Acks for our forfeits that nobody waits for
+    ignored_acks: usize,
+    // A FINISHED GAME IS NOT RESUMED WHEN THE CONNECTION DROPS
+    game_over: bool,
+}
+
+fn start_with_fen(start: &net::Start, fen: Option<String>) -> net::Start {
+    net::Start {
+        is_white: start.is_white,
+        name: start.name.clone(),
+        fen,
+        time: start.time,
+        inc: start.inc,
+    }
+}
+
+// Read and decode messages on a background thread, they arrive through the returned channel
+fn spawn_reader(stream: &TcpStream) -> Receiver<Result<Message, NetError>> {
+    let reader = stream.try_clone().expect("Could not clone stream");
+    let (tx, messages) = mpsc::channel();
+
+    std::thread::spawn(move || read_loop(reader, tx));
+
+    messages
 }
 
 impl Connection {
@@ -14,11 +85,7 @@ impl Connection {
             .accept()
             .expect("Could not bind to address");
 
-        stream
-            .set_nonblocking(true)
-            .expect("Could not set non-blocking");
-
-        Self { stream }
+        Self::start(stream, ConnectionType::Server, addr)
     }
 
     pub fn new_client(addr: &str) -> Self {
@@ -36,29 +103,94 @@ impl Connection {
             std::thread::sleep(Duration::from_secs(1));
         }
 
-        let stream = stream.unwrap();
+        Self::start(stream.unwrap(), ConnectionType::Client, addr)
+    }
 
-        stream
-            .set_nonblocking(true)
-            .expect("Could not set non-blocking");
+    fn start(stream: TcpStream, role: ConnectionType, addr: &str) -> Self {
+        Self {
+            messages: spawn_reader(&stream),
+            stream,
+            last_error: None,
+            role,
+            addr: addr.to_string(),
+            inbox: VecDeque::new(),
+            connected: true,
+            own_start: None,
+            fen: START_FEN.to_string(),
+            reconnecting: None,
+            resync: None,
+            ignored_acks: 0,
+            game_over: false,
+        }
+    }
 
-        Self { stream }
+    fn _send(&mut self, data: Vec<u8>) {
+        if !self.connected {
+            return;
+        }
+
+        if let Err(e) = self.stream.write_all(&data) {
+            log!(LogLevel::Error, "Could not write to stream: {}", e);
+            self.disconnected();
+        }
+    }
+
+    // Send our Start and keep it for the handshake when reconnecting
+    pub fn send_start(&mut self, start: net::Start) {
+        self.send(start_with_fen(&start, start.fen.clone()));
+        self.own_start = Some(start);
     }
 
-    fn _receive(&mut self) -> Vec<u8> {
-        let mut data = [0u8; 1024];
-        let res = self.stream.read(&mut data);
+    fn disconnected(&mut self) {
+        self.connected = false;
+        let _ = self.stream.shutdown(Shutdown::Both);
 
-        match res {
-            Ok(size) => data[..size].to_vec(),
-            Err(_) => Vec::new(),
+        if self.reconnecting.is_some() || self.game_over {
+            return;
         }
+
+        // NOTHING TO RESUME IF THE FIRST HANDSHAKE NEVER FINISHED
+        let Some(start) = &self.own_start else {
+            return;
+        };
+
+        let hello: Vec<u8> = match start_with_fen(start, Some(self.fen.clone())).try_into() {
+            Ok(hello) => hello,
+            Err(_) => return,
+        };
+
+        let (tx, rx) = mpsc::channel();
+        let role = self.role;
+        let addr = self.addr.clone();
+
+        std::thread::spawn(move || reconnect_loop(role, addr, hello, tx));
+
+        self.reconnecting = Some(rx);
     }
 
-    fn _send(&mut self, data: Vec<u8>) {
-        self.stream
-            .write_all(&data)
-            .expect("Could not write to stream");
+    fn poll_reconnect(&mut self) {
+        let Some(reconnecting) = &self.reconnecting else {
+            return;
+        };
+
+        let Ok((stream, peer)) = reconnecting.try_recv() else {
+            return;
+        };
+
+        log!(LogLevel::Info, "Reconnected: {:?}", peer);
+
+        self.messages = spawn_reader(&stream);
+        self.stream = stream;
+        self.inbox.clear();
+        self.connected = true;
+        self.reconnecting = None;
+        self.last_error = None;
+
+        // THE SERVER'S POSITION WINS
+        self.resync = match self.role {
+            ConnectionType::Server => Some(self.fen.clone()),
+            ConnectionType::Client => Some(peer.fen.unwrap_or(self.fen.clone())),
+        };
     }
 
     pub fn send<T>(&mut self, s: T)
@@ -75,52 +207,420 @@ impl Connection {
         self._send(data);
     }
 
-    pub fn receive<T>(&mut self) -> Option<T>
-    where
-        for<'a> T: TryFrom<&'a [u8]>,
-    {
-        let mut data = self._receive();
+    fn push(&mut self, msg: Result<Message, NetError>) {
+        match msg {
+            Ok(msg) => self.inbox.push_back(msg),
+            Err(NetError::Disconnected) => {
+                log!(LogLevel::Error, "Network error: {}", NetError::Disconnected);
+                self.disconnected();
+            }
+            Err(e) => {
+                log!(LogLevel::Error, "Network error: {}", e);
+                self.last_error = Some(e);
+            }
+        }
+    }
+
+    // Next received message matching `want`, other messages stay queued
+    fn take(&mut self, want: fn(&Message) -> bool) -> Option<Message> {
+        self.poll_reconnect();
 
-        if data.len() != 0 {
-            println!("Received: {:?}", data);
+        while let Ok(msg) = self.messages.try_recv() {
+            self.push(msg);
         }
 
-        let mut d = Vec::new();
+        let i = self.inbox.iter().position(want)?;
+        self.inbox.remove(i)
+    }
+
+    // Block until the peer's Start arrives, used for the handshake
+    pub fn wait_start(&mut self) -> Result<net::Start, String> {
+        loop {
+            if let Some(Message::Start(start)) = self.take(|m| matches!(m, Message::Start(_))) {
+                return Ok(start);
+            }
+
+            // A nack instead of a Start means the server refused our Start
+            if self
+                .take(|m| matches!(m, Message::Ack(ack) if !ack.ok))
+                .is_some()
+            {
+                return Err("the opponent rejected the game, check --fen".to_string());
+            }
+
+            match self.messages.recv() {
+                Ok(msg) => self.push(msg),
+                Err(_) => return Err("connection closed during handshake".to_string()),
+            }
+        }
+    }
+
+    // The protocol has no error message, a nack in place of our Start tells the client it was refused
+    pub fn reject_start(&mut self) {
+        self.send(net::Ack {
+            ok: false,
+            end_state: None,
+        });
+        let _ = self.stream.shutdown(Shutdown::Both);
+    }
+}
+
+fn read_len(buf: &[u8], at: usize, size: usize) -> Option<usize> {
+    let bytes = buf.get(at..at + size)?;
+    Some(bytes.iter().fold(0, |n, b| (n << 8) | *b as usize))
+}
+
+// Length of the first complete MessagePack value in `buf`, None if more bytes are needed
+fn frame_len(buf: &[u8]) -> Result<Option<usize>, NetError> {
+    let mut pos = 0;
+    let mut values = 1;
 
-        if data.len() == 1 {
-            loop {
-                std::thread::sleep(Duration::from_millis(10));
-                d = self._receive();
+    while values > 0 {
+        let Some(&marker) = buf.get(pos) else {
+            return Ok(None);
+        };
+        values -= 1;
+        pos += 1;
+
+        // BYTES TO SKIP AFTER THE MARKER AND NESTED VALUES THAT FOLLOW
+        let step = match marker {
+            0x00..=0x7f | 0xc0 | 0xc2 | 0xc3 | 0xe0..=0xff => Some((0, 0)),
+            0x80..=0x8f => Some((0, 2 * (marker & 0x0f) as usize)),
+            0x90..=0x9f => Some((0, (marker & 0x0f) as usize)),
+            0xa0..=0xbf => Some(((marker & 0x1f) as usize, 0)),
+            0xc4 | 0xd9 => read_len(buf, pos, 1).map(|n| (1 + n, 0)),
+            0xc5 | 0xda => read_len(buf, pos, 2).map(|n| (2 + n, 0)),
+            0xc6 | 0xdb => read_len(buf, pos, 4).map(|n| (4 + n, 0)),
+            0xc7 => read_len(buf, pos, 1).map(|n| (2 + n, 0)),
+            0xc8 => read_len(buf, pos, 2).map(|n| (3 + n, 0)),
+            0xc9 => read_len(buf, pos, 4).map(|n| (5 + n, 0)),
+            0xcc | 0xd0 => Some((1, 0)),
+            0xcd | 0xd1 => Some((2, 0)),
+            0xca | 0xce | 0xd2 => Some((4, 0)),
+            0xcb | 0xcf | 0xd3 => Some((8, 0)),
+            0xd4 => Some((2, 0)),
+            0xd5 => Some((3, 0)),
+            0xd6 => Some((5, 0)),
+            0xd7 => Some((9, 0)),
+            0xd8 => Some((17, 0)),
+            0xdc => read_len(buf, pos, 2).map(|n| (2, n)),
+            0xdd => read_len(buf, pos, 4).map(|n| (4, n)),
+            0xde => read_len(buf, pos, 2).map(|n| (2, 2 * n)),
+            0xdf => read_len(buf, pos, 4).map(|n| (4, 2 * n)),
+            0xc1 => return Err(NetError::InvalidFrame(marker)),
+        };
+
+        let Some((skip, nested)) = step else {
+            return Ok(None);
+        };
+        pos += skip;
+        values += nested;
+    }
 
-                if d.len() != 0 {
-                    println!("Received: {:?}", d);
-                    break;
+    if pos > buf.len() {
+        Ok(None)
+    } else {
+        Ok(Some(pos))
+    }
+}
+
+fn decode(data: &[u8]) -> Result<Message, NetError> {
+    let move_err = match net::Move::try_from(data) {
+        Ok(m) => return Ok(Message::Move(m)),
+        Err(e) => e,
+    };
+    let ack_err = match net::Ack::try_from(data) {
+        Ok(ack) => return Ok(Message::Ack(ack)),
+        Err(e) => e,
+    };
+    let start_err = match net::Start::try_from(data) {
+        Ok(start) => return Ok(Message::Start(start)),
+        Err(e) => e,
+    };
+
+    Err(NetError::Decode(format!(
+        "not a Move ({:?}), Ack ({:?}) or Start ({:?})",
+        move_err, ack_err, start_err
+    )))
+}
+
+fn read_loop(mut stream: TcpStream, tx: Sender<Result<Message, NetError>>) {
+    let mut buffer = Vec::new();
+    let mut chunk = [0u8; 1024];
+
+    loop {
+        let size = match stream.read(&mut chunk) {
+            Ok(0) | Err(_) => {
+                let _ = tx.send(Err(NetError::Disconnected));
+                return;
+            }
+            Ok(size) => size,
+        };
+
+        buffer.extend_from_slice(&chunk[..size]);
+
+        // SPLIT OFF EVERY COMPLETE MESSAGE, KEEP THE REST FOR THE NEXT READ
+        loop {
+            let msg = match frame_len(&buffer) {
+                Ok(Some(len)) => {
+                    let frame: Vec<u8> = buffer.drain(..len).collect();
+                    log!(LogLevel::Debug, "Received: {:?}", frame);
+                    decode(&frame)
+                }
+                Ok(None) if buffer.len() > MAX_FRAME => {
+                    buffer.clear();
+                    Err(NetError::FrameTooLarge)
+                }
+                Ok(None) => break,
+                Err(e) => {
+                    buffer.clear();
+                    Err(e)
                 }
+            };
+
+            if tx.send(msg).is_err() {
+                return;
             }
         }
+    }
+}
 
-        data.extend(d);
+// Block until a Start arrives on a fresh stream
+fn read_start(stream: &mut TcpStream) -> io::Result<net::Start> {
+    let mut buffer = Vec::new();
+    let mut chunk = [0u8; 1024];
 
-        let result = T::try_from(&data as &[u8]);
+    loop {
+        let size = stream.read(&mut chunk)?;
+        if size == 0 {
+            return Err(io::ErrorKind::UnexpectedEof.into());
+        }
+        buffer.extend_from_slice(&chunk[..size]);
 
-        match result {
-            Ok(data) => Some(data),
-            Err(_) => None,
+        let invalid = |e: NetError| io::Error::new(io::ErrorKind::InvalidData, e.to_string());
+
+        while let Some(len) = frame_len(&buffer).map_err(invalid)? {
+            let frame: Vec<u8> = buffer.drain(..len).collect();
+            if let Message::Start(start) = decode(&frame).map_err(invalid)? {
+                return Ok(start);
+            }
         }
     }
+}
 
-    pub fn receive_skibidi<T>(&mut self) -> T
-    where
-        for<'a> T: TryFrom<&'a [u8]>,
-    {
-        let mut res: Option<T>;
-        loop {
-            res = self.receive();
-            if res.is_some() {
-                break;
+// Wait for the peer to come back and redo the handshake, same roles as the first time
+fn reconnect_loop(
+    role: ConnectionType,
+    addr: String,
+    hello: Vec<u8>,
+    tx: Sender<(TcpStream, net::Start)>,
+) {
+    loop {
+        let stream = match role {
+            ConnectionType::Server => TcpListener::bind(&addr)
+                .and_then(|listener| listener.accept())
+                .map(|(stream, _)| stream),
+            ConnectionType::Client => TcpStream::connect(&addr),
+        };
+
+        let handshake = stream.and_then(|mut stream| {
+            let peer = match role {
+                ConnectionType::Server => {
+                    let peer = read_start(&mut stream)?;
+                    stream.write_all(&hello)?;
+                    peer
+                }
+                ConnectionType::Client => {
+                    stream.write_all(&hello)?;
+                    read_start(&mut stream)?
+                }
+            };
+            Ok((stream, peer))
+        });
+
+        match handshake {
+            Ok(reconnected) => {
+                let _ = tx.send(reconnected);
+                return;
+            }
+            Err(e) => {
+                log!(LogLevel::Warn, "Could not reconnect: {}", e);
+                std::thread::sleep(Duration::from_secs(1));
+            }
+        }
+    }
+}
+
+impl Opponent for Connection {
+    fn send_move(&mut self, mv: net::Move) {
+        if mv.forfeit {
+            self.ignored_acks += 1;
+        }
+
+        self.send(mv);
+    }
+
+    fn poll_ack(&mut self) -> Option<net::Ack> {
+        while self.ignored_acks > 0 && self.take(|m| matches!(m, Message::Ack(_))).is_some() {
+            self.ignored_acks -= 1;
+        }
+
+        match self.take(|m| matches!(m, Message::Ack(_))) {
+            Some(Message::Ack(ack)) => Some(ack),
+            _ => None,
+        }
+    }
+
+    fn poll_move(&mut self) -> Option<net::Move> {
+        match self.take(|m| matches!(m, Message::Move(_))) {
+            Some(Message::Move(m)) => Some(m),
+            _ => None,
+        }
+    }
+
+    fn ack(&mut self, ack: net::Ack) {
+        self.send(ack);
+    }
+
+    fn reports_end_state(&self) -> bool {
+        true
+    }
+
+    // Stop waiting for a peer that left, the reconnect thread gives up once it finds them
+    fn game_ended(&mut self) {
+        self.game_over = true;
+        self.reconnecting = None;
+    }
+
+    // The protocol has no way to start another game with the same peer
+    fn can_restart(&self) -> bool {
+        false
+    }
+
+    fn poll_forfeit(&mut self) -> bool {
+        let forfeit = self.take(|m| matches!(m, Message::Move(mv) if mv.forfeit));
+
+        if forfeit.is_some() {
+            self.ack(net::Ack {
+                ok: true,
+                end_state: None,
+            });
+        }
+
+        forfeit.is_some()
+    }
+
+    fn poll_error(&mut self) -> Option<String> {
+        self.poll_reconnect();
+
+        while let Ok(msg) = self.messages.try_recv() {
+            self.push(msg);
+        }
+
+        self.last_error.take().map(|e| e.to_string())
+    }
+
+    fn poll_resync(&mut self) -> Option<String> {
+        self.poll_reconnect();
+        self.resync.take()
+    }
+
+    fn problem(&self) -> Option<String> {
+        if self.connected || self.game_over {
+            return None;
+        }
+
+        match self.role {
+            ConnectionType::Server => {
+                Some("Opponent disconnected, waiting for them...".to_string())
             }
+            ConnectionType::Client => Some("Disconnected, reconnecting...".to_string()),
+        }
+    }
+
+    fn position_changed(&mut self, position: &PositionInfo) {
+        self.fen = position_fen(
+            position.board_str,
+            position.turn,
+            position.start_fen,
+            position.moves,
+        );
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn encoded_move() -> Vec<u8> {
+        net::Move {
+            from: (4, 1),
+            to: (4, 3),
+            promotion: None,
+            forfeit: false,
+            offer_draw: false,
+        }
+        .try_into()
+        .unwrap()
+    }
+
+    fn encoded_ack() -> Vec<u8> {
+        net::Ack {
+            ok: true,
+            end_state: Some(net::GameState::Draw),
+        }
+        .try_into()
+        .unwrap()
+    }
+
+    #[test]
+    fn split_message_needs_more_bytes() {
+        let data = encoded_move();
+
+        for end in 0..data.len() {
+            assert!(matches!(frame_len(&data[..end]), Ok(None)), "{}", end);
         }
+        assert!(matches!(frame_len(&data), Ok(Some(n)) if n == data.len()));
+    }
+
+    #[test]
+    fn coalesced_messages_split_one_at_a_time() {
+        let (mv, ack) = (encoded_move(), encoded_ack());
+        let data = [mv.clone(), ack.clone()].concat();
+
+        let Ok(Some(first)) = frame_len(&data) else {
+            panic!("first message not found");
+        };
+        assert_eq!(first, mv.len());
+        assert!(matches!(decode(&data[..first]), Ok(Message::Move(_))));
+
+        let rest = &data[first..];
+        assert!(matches!(frame_len(rest), Ok(Some(n)) if n == ack.len()));
+        assert!(matches!(decode(rest), Ok(Message::Ack(_))));
+    }
+
+    #[test]
+    fn nested_values_are_counted() {
+        // {"a": [1, {"b": nil}], "c": array16 [true, false]} and one byte of the next message
+        let data = [
+            0x82, 0xa1, b'a', 0x92, 0x01, 0x81, 0xa1, b'b', 0xc0, 0xa1, b'c', 0xdc, 0x00, 0x02,
+            0xc3, 0xc2, 0x93,
+        ];
+
+        assert!(matches!(frame_len(&data), Ok(Some(16))));
+        assert!(matches!(frame_len(&data[..15]), Ok(None)));
+    }
+
+    #[test]
+    fn oversized_and_invalid_frames() {
+        // bin32 announcing a megabyte only waits for more, read_loop gives up at MAX_FRAME
+        let header = [0xc6, 0x00, 0x10, 0x00, 0x00, 0x01];
+        assert!(matches!(frame_len(&header), Ok(None)));
 
-        res.unwrap()
+        assert!(matches!(
+            frame_len(&[0xc1, 0x00]),
+            Err(NetError::InvalidFrame(0xc1))
+        ));
+        assert!(matches!(decode(&[0x01]), Err(NetError::Decode(_))));
     }
 }
@@ -0,0 +1,87 @@
+use crate::*;
+
+// What an opponent gets to see of the game after every change
+pub struct PositionInfo<'a> {
+    pub start_fen: Option<&'a str>,
+    pub moves: &'a [PlayedMove],
+    pub board_str: &'a str,
+    pub turn: Color,
+    pub valid_moves: &'a [Vec<usize>; 64],
+}
+
+// What to do when the opponent keeps sending illegal moves
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum IllegalMovePolicy {
+    Warn,
+    Ignore,
+    End,
+}
+
+impl FromStr for IllegalMovePolicy {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "warn" => Ok(IllegalMovePolicy::Warn),
+            "ignore" => Ok(IllegalMovePolicy::Ignore),
+            "end" => Ok(IllegalMovePolicy::End),
+            _ => Err(format!("unknown policy {}, must be warn, ignore or end", s)),
+        }
+    }
+}
+
+// Where the moves of the other side come from
+pub trait Opponent {
+    // Offer our move to the opponent
+    fn send_move(&mut self, _mv: net::Move) {}
+
+    // The opponent's answer to our last move, None while still waiting
+    fn poll_ack(&mut self) -> Option<net::Ack> {
+        Some(net::Ack {
+            ok: true,
+            end_state: None,
+        })
+    }
+
+    // The opponent's next move, if it has made one
+    fn poll_move(&mut self) -> Option<net::Move>;
+
+    // Tell the opponent whether its last move was accepted and how the game stands
+    fn ack(&mut self, ack: net::Ack);
+
+    // Whether acks carry the opponent's own adjudication of the game
+    fn reports_end_state(&self) -> bool {
+        false
+    }
+
+    // The game has ended, nothing more will be played against this opponent
+    fn game_ended(&mut self) {}
+
+    // Whether a new game can follow a finished one
+    fn can_restart(&self) -> bool {
+        true
+    }
+
+    // Whether the opponent gave up, checked on every frame
+    fn poll_forfeit(&mut self) -> bool {
+        false
+    }
+
+    // Position to continue from after a lost connection was restored, pending moves are dropped
+    fn poll_resync(&mut self) -> Option<String> {
+        None
+    }
+
+    // Problem with the opponent to show in the UI
+    fn problem(&self) -> Option<String> {
+        None
+    }
+
+    // A message from the opponent that could not be read, taken once
+    fn poll_error(&mut self) -> Option<String> {
+        None
+    }
+
+    // Called whenever the position on the board changes
+    fn position_changed(&mut self, _position: &PositionInfo) {}
+}
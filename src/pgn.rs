@@ -0,0 +1,357 @@
+use crate::*;
+use std::time::{SystemTime, UNIX_EPOCH};
+
+pub fn pgn_result(status: Status, winner: Option<Color>) -> &'static str {
+    match (status, winner) {
+        (Status::Active, _) => "*",
+        (_, Some(Color::White)) => "1-0",
+        (_, Some(Color::Black)) => "0-1",
+        _ => "1/2-1/2",
+    }
+}
+
+fn unix_secs() -> u64 {
+    SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map(|d| d.as_secs())
+        .unwrap_or(0)
+}
+
+// Today's date as YYYY.MM.DD
+pub fn pgn_date() -> String {
+    // DAYS SINCE EPOCH TO CIVIL DATE, SEE http://howardhinnant.github.io/date_algorithms.html
+    let z = (unix_secs() / 86400) as i64 + 719468;
+    let era = z.div_euclid(146097);
+    let doe = z.rem_euclid(146097);
+    let yoe = (doe - doe / 1460 + doe / 36524 - doe / 146096) / 365;
+    let doy = doe - (365 * yoe + yoe / 4 - yoe / 100);
+    let mp = (5 * doy + 2) / 153;
+    let d = doy - (153 * mp + 2) / 5 + 1;
+    let m = if mp < 10 { mp + 3 } else { mp - 9 };
+    let y = yoe + era * 400 + if m <= 2 { 1 } else { 0 };
+
+    format!("{:04}.{:02}.{:02}", y, m, d)
+}
+
+pub fn default_pgn_path() -> String {
+    format!("game_{}.pgn", unix_secs())
+}
+
+// Lines of the move list, the move number with the indices of the white and black move
+pub fn numbered_moves(
+    start_fen: Option<&str>,
+    count: usize,
+) -> Vec<(u32, Option<usize>, Option<usize>)> {
+    let fields: Vec<&str> = start_fen.unwrap_or(START_FEN).split_whitespace().collect();
+    let black_first = fields.get(1) == Some(&"b");
+    let first_number: u32 = fields.get(5).and_then(|n| n.parse().ok()).unwrap_or(1);
+
+    // A GAME STARTING WITH BLACK LEAVES THE FIRST WHITE SLOT EMPTY, ONCE BLACK HAS MOVED
+    let offset = (black_first && count > 0) as usize;
+    let slots = count + offset;
+
+    (0..slots.div_ceil(2))
+        .map(|row| {
+            let white = (2 * row).checked_sub(offset).filter(|&i| i < count);
+            let black = (2 * row + 1 - offset < count).then_some(2 * row + 1 - offset);
+            (first_number + row as u32, white, black)
+        })
+        .collect()
+}
+
+// Tag values escape backslashes and quotes with a backslash
+fn escape_tag(value: &str) -> String {
+    value.replace('\\', "\\\\").replace('"', "\\\"")
+}
+
+pub fn write_pgn(
+    white: &str,
+    black: &str,
+    result: &str,
+    start_fen: Option<&str>,
+    moves: &[PlayedMove],
+) -> String {
+    let mut pgn = String::new();
+
+    // SEVEN TAG ROSTER
+    pgn.push_str("[Event \"Casual game\"]\n");
+    pgn.push_str("[Site \"?\"]\n");
+    pgn.push_str(&format!("[Date \"{}\"]\n", pgn_date()));
+    pgn.push_str("[Round \"-\"]\n");
+    pgn.push_str(&format!("[White \"{}\"]\n", escape_tag(white)));
+    pgn.push_str(&format!("[Black \"{}\"]\n", escape_tag(black)));
+    pgn.push_str(&format!("[Result \"{}\"]\n", result));
+
+    let mut move_number = 1;
+    let mut white_to_move = true;
+
+    if let Some(fen) = start_fen {
+        pgn.push_str("[SetUp \"1\"]\n");
+        pgn.push_str(&format!("[FEN \"{}\"]\n", fen));
+
+        let fields: Vec<&str> = fen.split_whitespace().collect();
+        white_to_move = fields[1] == "w";
+        move_number = fields.get(5).and_then(|n| n.parse().ok()).unwrap_or(1);
+    }
+
+    pgn.push('\n');
+
+    let mut tokens = Vec::new();
+
+    for (i, m) in moves.iter().enumerate() {
+        if white_to_move {
+            tokens.push(format!("{}.", move_number));
+        } else if i == 0 {
+            tokens.push(format!("{}...", move_number));
+        }
+
+        tokens.push(m.san.clone());
+
+        if !white_to_move {
+            move_number += 1;
+        }
+        white_to_move = !white_to_move;
+    }
+
+    tokens.push(result.to_string());
+
+    // WRAP MOVETEXT AT 80 CHARACTERS
+    let mut line_len = 0;
+    for token in tokens {
+        if line_len > 0 && line_len + token.len() + 1 > 80 {
+            pgn.push('\n');
+            line_len = 0;
+        } else if line_len > 0 {
+            pgn.push(' ');
+            line_len += 1;
+        }
+        line_len += token.len();
+        pgn.push_str(&token);
+    }
+
+    pgn.push('\n');
+    pgn
+}
+
+pub struct PgnGame {
+    pub tags: Vec<(String, String)>,
+    pub moves: Vec<String>,
+}
+
+impl PgnGame {
+    pub fn tag(&self, name: &str) -> Option<&str> {
+        self.tags
+            .iter()
+            .find(|(key, _)| key == name)
+            .map(|(_, value)| value.as_str())
+    }
+
+    pub fn title(&self) -> String {
+        format!(
+            "{} vs {} ({})",
+            self.tag("White").unwrap_or("?"),
+            self.tag("Black").unwrap_or("?"),
+            self.tag("Result").unwrap_or("*")
+        )
+    }
+}
+
+fn parse_tag(line: &str) -> Option<(String, String)> {
+    let inner = line.strip_prefix('[')?.strip_suffix(']')?;
+    let (key, value) = inner.split_once(' ')?;
+    let value = value.trim().strip_prefix('"')?.strip_suffix('"')?;
+
+    let mut text = String::new();
+    let mut chars = value.chars();
+    while let Some(c) = chars.next() {
+        match c {
+            '\\' => text.extend(chars.next()),
+            c => text.push(c),
+        }
+    }
+
+    Some((key.to_string(), text))
+}
+
+// Split movetext into SAN moves, skipping move numbers, comments, variations and NAGs
+fn parse_movetext(movetext: &str) -> Vec<String> {
+    let mut moves = Vec::new();
+    let mut token = String::new();
+    let mut comment = false;
+    let mut depth = 0;
+
+    for c in movetext.chars().chain(std::iter::once(' ')) {
+        match c {
+            '{' if depth == 0 => comment = true,
+            '}' => comment = false,
+            _ if comment => (),
+            '(' => depth += 1,
+            ')' => depth -= 1,
+            _ if depth > 0 => (),
+            c if c.is_whitespace() || c == '.' => {
+                let is_move = !token.is_empty()
+                    && !token.starts_with('$')
+                    && !token.chars().all(|c| c.is_ascii_digit())
+                    && !["1-0", "0-1", "1/2-1/2", "*"].contains(&token.as_str());
+
+                if is_move {
+                    moves.push(token.clone());
+                }
+                token.clear();
+            }
+            c => token.push(c),
+        }
+    }
+
+    moves
+}
+
+pub fn parse_pgn(text: &str) -> Vec<PgnGame> {
+    let mut games = Vec::new();
+    let mut tags = Vec::new();
+    let mut movetext = String::new();
+
+    for line in text.lines() {
+        let line = line.trim();
+
+        if line.starts_with('[') {
+            // A TAG AFTER MOVETEXT STARTS A NEW GAME
+            if !movetext.trim().is_empty() {
+                let moves = parse_movetext(&movetext);
+                games.push(PgnGame { tags, moves });
+                tags = Vec::new();
+                movetext.clear();
+            }
+
+            if let Some(tag) = parse_tag(line) {
+                tags.push(tag);
+            }
+        } else {
+            // SKIP REST OF LINE COMMENTS
+            let line = line.split(';').next().unwrap_or("");
+            movetext.push_str(line);
+            movetext.push('\n');
+        }
+    }
+
+    if !tags.is_empty() || !movetext.trim().is_empty() {
+        let moves = parse_movetext(&movetext);
+        games.push(PgnGame { tags, moves });
+    }
+
+    games
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::chess::tests::{board, find, play, valid};
+
+    fn idx(from: &str, to: &str) -> (usize, usize) {
+        (str_to_idx(from), str_to_idx(to))
+    }
+
+    // A move, with the other legal moves the replay has to tell it apart from
+    type Step = (
+        &'static str,
+        &'static str,
+        &'static [(&'static str, &'static str)],
+    );
+
+    fn movetext(pgn: &str) -> &str {
+        pgn.lines()
+            .find(|line| !line.is_empty() && !line.starts_with('['))
+            .unwrap_or("")
+    }
+
+    #[test]
+    fn written_games_parse_and_replay() {
+        let line: [Step; 8] = [
+            ("e2", "e4", &[("d2", "d4"), ("g1", "f3")]),
+            ("e7", "e5", &[("c7", "c5")]),
+            ("g1", "f3", &[("b1", "c3"), ("g1", "h3")]),
+            ("b8", "c6", &[("g8", "f6")]),
+            ("f1", "c4", &[("f1", "b5")]),
+            ("g8", "f6", &[("f8", "c5")]),
+            ("e1", "g1", &[("d1", "e2")]),
+            ("f6", "e4", &[("c6", "d4"), ("f6", "g4")]),
+        ];
+        let start = board("rnbqkbnr/pppppppp/8/8/8/8/PPPPPPPP/RNBQKBNR");
+
+        let mut b = start.clone();
+        let mut moves = Vec::new();
+        for (from, to, others) in line {
+            let v = valid(&[&[(from, to)], others].concat());
+            let (from_idx, to_idx) = idx(from, to);
+            moves.push(PlayedMove {
+                from: from_idx,
+                to: to_idx,
+                promotion: None,
+                san: move_to_san(&b, &v, from_idx, to_idx, None),
+            });
+            b = play(&b, from, to);
+        }
+        let end = b;
+
+        let pgn = write_pgn(r#"Ann "The Rook" \ 2"#, "Bob", "*", None, &moves);
+        assert!(pgn.contains(r#"[White "Ann \"The Rook\" \\ 2"]"#));
+        assert_eq!(
+            movetext(&pgn),
+            "1. e4 e5 2. Nf3 Nc6 3. Bc4 Nf6 4. O-O Nxe4 *"
+        );
+
+        let games = parse_pgn(&pgn);
+        assert_eq!(games.len(), 1);
+        assert_eq!(games[0].tag("White"), Some(r#"Ann "The Rook" \ 2"#));
+        assert_eq!(games[0].tag("FEN"), None);
+        assert_eq!(games[0].title(), r#"Ann "The Rook" \ 2 vs Bob (*)"#);
+
+        let mut b = start;
+        for (san, (from, to, others)) in games[0].moves.iter().zip(line) {
+            let v = valid(&[&[(from, to)], others].concat());
+            assert_eq!(
+                find(&b, &v, san),
+                Some((from.to_string(), to.to_string(), None))
+            );
+            b = play(&b, from, to);
+        }
+        assert_eq!(games[0].moves.len(), line.len());
+        assert_eq!(b, end);
+    }
+
+    #[test]
+    fn games_where_black_moves_first() {
+        let fen = "4k3/8/8/8/8/8/8/4K3 b - - 0 12";
+        let moves: Vec<PlayedMove> = [
+            ("e8", "d7", "Kd7"),
+            ("e1", "e2", "Ke2"),
+            ("d7", "e6", "Ke6"),
+        ]
+        .iter()
+        .map(|&(from, to, san)| PlayedMove {
+            from: str_to_idx(from),
+            to: str_to_idx(to),
+            promotion: None,
+            san: san.to_string(),
+        })
+        .collect();
+
+        let pgn = write_pgn("White", "Black", "1/2-1/2", Some(fen), &moves);
+        assert_eq!(movetext(&pgn), "12... Kd7 13. Ke2 Ke6 1/2-1/2");
+
+        let games = parse_pgn(&pgn);
+        assert_eq!(games[0].tag("SetUp"), Some("1"));
+        assert_eq!(games[0].tag("FEN"), Some(fen));
+        assert_eq!(games[0].moves, ["Kd7", "Ke2", "Ke6"]);
+
+        assert_eq!(
+            numbered_moves(Some(fen), 3),
+            [(12, None, Some(0)), (13, Some(1), Some(2))]
+        );
+        assert_eq!(numbered_moves(Some(fen), 0), []);
+        assert_eq!(
+            numbered_moves(None, 3),
+            [(1, Some(0), Some(1)), (2, Some(2), None)]
+        );
+    }
+}
@@ -0,0 +1,168 @@
+use crate::*;
+
+const WINDOW_SIZES: [(f32, f32); 5] = [
+    (800.0, 600.0),
+    (1000.0, 800.0),
+    (1200.0, 1000.0),
+    (1400.0, 1200.0),
+    (1600.0, 1400.0),
+];
+
+const ACCENTS: [(u8, u8, u8); 5] = [
+    (199, 38, 239),
+    (255, 140, 0),
+    (0, 180, 220),
+    (230, 200, 0),
+    (60, 200, 90),
+];
+
+const ANIMATIONS: [u64; 5] = [0, 100, 200, 300, 500];
+
+const COLORS: [Option<ColorPreference>; 4] = [
+    None,
+    Some(ColorPreference::White),
+    Some(ColorPreference::Black),
+    Some(ColorPreference::Random),
+];
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum Setting {
+    Name,
+    Color,
+    Port,
+    Window,
+    Margin,
+    Theme,
+    Pieces,
+    Accent,
+    Animation,
+}
+
+pub const SETTINGS: [Setting; 9] = [
+    Setting::Name,
+    Setting::Color,
+    Setting::Port,
+    Setting::Window,
+    Setting::Margin,
+    Setting::Theme,
+    Setting::Pieces,
+    Setting::Accent,
+    Setting::Animation,
+];
+
+// Next entry of a list in the direction of step, wrapping around
+fn cycle<T: PartialEq + Copy>(list: &[T], current: T, step: i32) -> T {
+    let i = list.iter().position(|x| *x == current).unwrap_or(0) as i32;
+    let len = list.len() as i32;
+    list[(i + step).rem_euclid(len) as usize]
+}
+
+// The settings screen edits a copy of the config that is applied on save
+pub struct SettingsScreen {
+    pub draft: Config,
+    pub row: usize,
+    // PIECE SETS FOUND IN resources/ WHEN THE SCREEN WAS OPENED
+    pub piece_sets: Vec<String>,
+}
+
+impl SettingsScreen {
+    pub fn new(config: &Config, piece_sets: Vec<String>) -> Self {
+        SettingsScreen {
+            draft: config.clone(),
+            row: 0,
+            piece_sets,
+        }
+    }
+
+    pub fn setting(&self) -> Setting {
+        SETTINGS[self.row]
+    }
+
+    pub fn label(&self, setting: Setting) -> String {
+        let draft = &self.draft;
+
+        match setting {
+            Setting::Name => format!("Name: {}", draft.name.as_deref().unwrap_or("")),
+            Setting::Color => format!("Color: {}", draft.color.map_or("any", |c| c.name())),
+            Setting::Port => format!("Port: {}", draft.port),
+            Setting::Window => format!("Window: {}x{}", draft.window.0, draft.window.1),
+            Setting::Margin => format!("Margin: {}", draft.margin),
+            Setting::Theme => format!("Theme: {}", draft.theme),
+            Setting::Pieces => format!("Pieces: {}", draft.pieces),
+            Setting::Accent => {
+                let (r, g, b) = draft.accent;
+                format!("Accent: #{:02x}{:02x}{:02x}", r, g, b)
+            }
+            Setting::Animation if draft.animation == 0 => "Animation: off".to_string(),
+            Setting::Animation => format!("Animation: {} ms", draft.animation),
+        }
+    }
+
+    pub fn up(&mut self) {
+        self.row = self.row.saturating_sub(1);
+    }
+
+    pub fn down(&mut self) {
+        self.row = (self.row + 1).min(SETTINGS.len() - 1);
+    }
+
+    // Left and right arrows
+    pub fn change(&mut self, step: i32) {
+        let setting = self.setting();
+        let draft = &mut self.draft;
+
+        match setting {
+            Setting::Name => (),
+            Setting::Color => draft.color = cycle(&COLORS, draft.color, step),
+            Setting::Port => draft.port = draft.port.wrapping_add_signed(step as i16),
+            Setting::Window => draft.window = cycle(&WINDOW_SIZES, draft.window, step),
+            Setting::Margin => {
+                draft.margin =
+                    (draft.margin + 10.0 * step as f32).clamp(MARGIN_RANGE.0, MARGIN_RANGE.1)
+            }
+            Setting::Theme => {
+                let names: Vec<&str> = THEMES.iter().map(|theme| theme.name).collect();
+                draft.theme = cycle(&names, draft.theme.as_str(), step).to_string();
+            }
+            Setting::Pieces => {
+                let names: Vec<&str> = self.piece_sets.iter().map(String::as_str).collect();
+                draft.pieces = cycle(&names, draft.pieces.as_str(), step).to_string();
+            }
+            Setting::Accent => draft.accent = cycle(&ACCENTS, draft.accent, step),
+            Setting::Animation => draft.animation = cycle(&ANIMATIONS, draft.animation, step),
+        }
+    }
+
+    // Typing edits the name and the port
+    pub fn type_char(&mut self, c: char) {
+        match self.setting() {
+            Setting::Name if !c.is_control() => {
+                self.draft.name.get_or_insert_with(String::new).push(c);
+            }
+            Setting::Port => {
+                if let Some(port) = c
+                    .to_digit(10)
+                    .and_then(|d| (self.draft.port as u32 * 10 + d).try_into().ok())
+                {
+                    self.draft.port = port;
+                }
+            }
+            _ => (),
+        }
+    }
+
+    pub fn backspace(&mut self) {
+        match self.setting() {
+            Setting::Name => {
+                if let Some(name) = &mut self.draft.name {
+                    name.pop();
+                    if name.is_empty() {
+                        self.draft.name = None;
+                    }
+                }
+            }
+            Setting::Port => self.draft.port /= 10,
+            _ => (),
+        }
+    }
+}
@@ -0,0 +1,129 @@
+use crate::*;
+
+// Colors of the squares, the markers on them, the promotion dialog and the window behind the board
+pub struct Theme {
+    pub name: &'static str,
+    pub light: (u8, u8, u8),
+    pub dark: (u8, u8, u8),
+    pub check: (u8, u8, u8, u8),
+    pub last_move: (u8, u8, u8, u8),
+    // Arrows and circles drawn with the right button
+    pub annotation: (u8, u8, u8, u8),
+    pub promotion: (u8, u8, u8),
+    pub background: (u8, u8, u8),
+}
+
+pub const THEMES: [Theme; 4] = [
+    Theme {
+        name: "classic",
+        light: (255, 255, 255),
+        dark: (0, 0, 0),
+        check: (255, 0, 0, 100),
+        last_move: (255, 200, 0, 90),
+        annotation: (255, 140, 0, 180),
+        promotion: (200, 200, 200),
+        background: (0, 0, 0),
+    },
+    Theme {
+        name: "brown",
+        light: (240, 217, 181),
+        dark: (181, 136, 99),
+        check: (220, 40, 20, 140),
+        last_move: (205, 210, 106, 150),
+        annotation: (21, 120, 27, 170),
+        promotion: (250, 240, 220),
+        background: (46, 33, 24),
+    },
+    Theme {
+        name: "green",
+        light: (238, 238, 210),
+        dark: (118, 150, 86),
+        check: (230, 50, 50, 140),
+        last_move: (246, 246, 105, 130),
+        annotation: (255, 170, 0, 170),
+        promotion: (245, 245, 230),
+        background: (38, 46, 32),
+    },
+    Theme {
+        name: "blue",
+        light: (222, 227, 230),
+        dark: (140, 162, 173),
+        check: (230, 60, 90, 140),
+        last_move: (100, 180, 230, 110),
+        annotation: (230, 120, 30, 170),
+        promotion: (240, 244, 246),
+        background: (28, 36, 46),
+    },
+];
+
+// The pieces directly in resources/, used for any piece missing from another set
+pub const DEFAULT_PIECES: &str = "default";
+
+pub fn find_theme(name: &str) -> Option<&'static Theme> {
+    THEMES.iter().find(|theme| theme.name == name)
+}
+
+impl Theme {
+    pub fn light_color(&self) -> graphics::Color {
+        graphics::Color::from_rgb(self.light.0, self.light.1, self.light.2)
+    }
+
+    pub fn dark_color(&self) -> graphics::Color {
+        graphics::Color::from_rgb(self.dark.0, self.dark.1, self.dark.2)
+    }
+
+    pub fn check_color(&self) -> graphics::Color {
+        let (r, g, b, a) = self.check;
+        graphics::Color::from_rgba(r, g, b, a)
+    }
+
+    pub fn last_move_color(&self) -> graphics::Color {
+        let (r, g, b, a) = self.last_move;
+        graphics::Color::from_rgba(r, g, b, a)
+    }
+
+    pub fn annotation_color(&self) -> graphics::Color {
+        let (r, g, b, a) = self.annotation;
+        graphics::Color::from_rgba(r, g, b, a)
+    }
+
+    pub fn promotion_color(&self) -> graphics::Color {
+        graphics::Color::from_rgb(self.promotion.0, self.promotion.1, self.promotion.2)
+    }
+
+    pub fn background_color(&self) -> graphics::Color {
+        graphics::Color::from_rgb(self.background.0, self.background.1, self.background.2)
+    }
+}
+
+// Every subdirectory of resources/ is a piece set named after it
+pub fn piece_sets(ctx: &Context) -> Vec<String> {
+    let mut sets = vec![DEFAULT_PIECES.to_string()];
+
+    let dirs = match ctx.fs.read_dir("/") {
+        Ok(dirs) => dirs,
+        Err(e) => {
+            log!(LogLevel::Warn, "Could not list piece sets: {}", e);
+            return sets;
+        }
+    };
+
+    let mut names: Vec<String> = dirs
+        .filter(|path| ctx.fs.is_dir(path))
+        .filter_map(|path| Some(path.file_name()?.to_str()?.to_string()))
+        .collect();
+    names.sort();
+    names.dedup();
+
+    sets.extend(names.into_iter().filter(|name| name != DEFAULT_PIECES));
+    sets
+}
+
+// Resource path of a piece image in a set
+pub fn piece_path(set: &str, file: &str) -> String {
+    if set == DEFAULT_PIECES {
+        format!("/{}", file)
+    } else {
+        format!("/{}/{}", set, file)
+    }
+}